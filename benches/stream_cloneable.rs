@@ -6,29 +6,40 @@ extern crate futures;
 extern crate tokio_core;
 
 use futures::{Future, Stream};
+use futures::future::{ok, lazy};
 
 use ex_futures::StreamExt;
 
 use test::Bencher;
 
 
+/// Runs `f` in a task, because clones park it when they have no item.
+fn in_task<F: FnOnce()>(f: F) {
+    lazy(|| {
+        f();
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
 #[bench]
 fn normal(b: &mut Bencher) {
     let (tx, mut rx) = futures::sync::mpsc::unbounded();
-    b.iter(move || {
+    in_task(|| b.iter(move || {
         tx.unbounded_send(42).unwrap();
         rx.poll()
-    });
+    }));
 }
 
 #[bench]
 fn one_consumer(b: &mut Bencher) {
     let (tx, rx) = futures::sync::mpsc::unbounded();
     let mut cloneable = rx.cloneable();
-    b.iter(move || {
+    in_task(|| b.iter(move || {
         tx.unbounded_send(42).unwrap();
         cloneable.poll()
-    });
+    }));
 }
 
 #[bench]
@@ -36,12 +47,12 @@ fn two_consumer(b: &mut Bencher) {
     let (tx, rx) = futures::sync::mpsc::unbounded();
     let mut cloneable_rx = rx.cloneable();
     let mut cloneable_rx2 = cloneable_rx.clone();;
-    b.iter(move || {
+    in_task(|| b.iter(move || {
         tx.unbounded_send(42).unwrap();
         let _item = cloneable_rx.poll().unwrap();
         let _item2 = cloneable_rx2.poll().unwrap();
         (_item, _item2)
-    });
+    }));
 }
 
 #[bench]
@@ -50,11 +61,51 @@ fn three_consumer(b: &mut Bencher) {
     let mut cloneable_rx = rx.cloneable();
     let mut cloneable_rx2 = cloneable_rx.clone();;
     let mut cloneable_rx3 = cloneable_rx.clone();;
-    b.iter(move || {
+    in_task(|| b.iter(move || {
         tx.unbounded_send(42).unwrap();
         let _item = cloneable_rx.poll().unwrap();
         let _item2 = cloneable_rx2.poll().unwrap();
         let _item3 = cloneable_rx3.poll().unwrap();
         (_item, _item2, _item3)
-    });
+    }));
+}
+
+#[bench]
+fn ten_consumer(b: &mut Bencher) {
+    let (tx, rx) = futures::sync::mpsc::unbounded();
+    let cloneable_rx = rx.cloneable();
+    let mut consumers = vec![cloneable_rx.clone(); 10];
+    drop(cloneable_rx);
+    in_task(|| b.iter(move || {
+        tx.unbounded_send(42).unwrap();
+        consumers
+            .iter_mut()
+            .map(|rx| rx.poll().unwrap())
+            .collect::<Vec<_>>()
+    }));
+}
+
+#[bench]
+fn hundred_consumer(b: &mut Bencher) {
+    let (tx, rx) = futures::sync::mpsc::unbounded();
+    let cloneable_rx = rx.cloneable();
+    let mut consumers = vec![cloneable_rx.clone(); 100];
+    drop(cloneable_rx);
+    in_task(|| b.iter(move || {
+        tx.unbounded_send(42).unwrap();
+        consumers
+            .iter_mut()
+            .map(|rx| rx.poll().unwrap())
+            .collect::<Vec<_>>()
+    }));
+}
+
+#[bench]
+fn large_history(b: &mut Bencher) {
+    let (tx, rx) = futures::sync::mpsc::unbounded();
+    let mut replayable = rx.replayable(10_000);
+    in_task(|| b.iter(move || {
+        tx.unbounded_send(42).unwrap();
+        replayable.poll()
+    }));
 }
@@ -6,29 +6,40 @@ extern crate futures;
 extern crate tokio_core;
 
 use futures::{Future, Stream};
+use futures::future::{ok, lazy};
 
 use ex_futures::StreamExt;
 
 use test::Bencher;
 
 
+/// Runs `f` in a task, because clones park it when they have no item.
+fn in_task<F: FnOnce()>(f: F) {
+    lazy(|| {
+        f();
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
 #[bench]
 fn normal(b: &mut Bencher) {
     let (tx, mut rx) = futures::unsync::mpsc::unbounded();
-    b.iter(move || {
+    in_task(|| b.iter(move || {
         tx.unbounded_send(42).unwrap();
         rx.poll()
-    });
+    }));
 }
 
 #[bench]
 fn one_consumer(b: &mut Bencher) {
     let (tx, rx) = futures::unsync::mpsc::unbounded();
     let mut cloneable = rx.unsync_cloneable();
-    b.iter(move || {
+    in_task(|| b.iter(move || {
         tx.unbounded_send(42).unwrap();
         cloneable.poll()
-    });
+    }));
 }
 
 #[bench]
@@ -36,12 +47,12 @@ fn two_consumer(b: &mut Bencher) {
     let (tx, rx) = futures::unsync::mpsc::unbounded();
     let mut cloneable_rx = rx.unsync_cloneable();
     let mut cloneable_rx2 = cloneable_rx.clone();;
-    b.iter(move || {
+    in_task(|| b.iter(move || {
         tx.unbounded_send(42).unwrap();
         let _item = cloneable_rx.poll().unwrap();
         let _item2 = cloneable_rx2.poll().unwrap();
         (_item, _item2)
-    });
+    }));
 }
 
 #[bench]
@@ -50,11 +61,24 @@ fn three_consumer(b: &mut Bencher) {
     let mut cloneable_rx = rx.unsync_cloneable();
     let mut cloneable_rx2 = cloneable_rx.clone();;
     let mut cloneable_rx3 = cloneable_rx.clone();;
-    b.iter(move || {
+    in_task(|| b.iter(move || {
         tx.unbounded_send(42).unwrap();
         let _item = cloneable_rx.poll().unwrap();
         let _item2 = cloneable_rx2.poll().unwrap();
         let _item3 = cloneable_rx3.poll().unwrap();
         (_item, _item2, _item3)
-    });
+    }));
+}
+
+#[bench]
+fn churny_consumer(b: &mut Bencher) {
+    let (tx, rx) = futures::unsync::mpsc::unbounded();
+    let mut cloneable_rx = rx.unsync_cloneable();
+    in_task(|| b.iter(move || {
+        let mut cloneable_rx2 = cloneable_rx.clone();
+        tx.unbounded_send(42).unwrap();
+        let _item = cloneable_rx.poll().unwrap();
+        let _item2 = cloneable_rx2.poll().unwrap();
+        (_item, _item2)
+    }));
 }
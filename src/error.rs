@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::fmt;
+
+
+
+/// An error of streams and sinks which share their source among threads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SharedError<E> {
+    /// An error of original stream or sink.
+    Inner(E),
+
+    /// Other thread panicked while it was using original stream or sink.
+    /// After this error, the stream finishes.
+    Poisoned,
+
+    /// This clone fell behind others and was detached from shared source.
+    /// After this error, the stream finishes.
+    ///
+    /// Only streams created by `cloneable_evicting` return this. Forks and cloneable sinks never
+    /// evict, so they never return it.
+    Evicted,
+}
+
+
+impl<E> SharedError<E> {
+    /// Returns `true` if this is `SharedError::Poisoned`.
+    pub fn is_poisoned(&self) -> bool {
+        matches!(*self, SharedError::Poisoned)
+    }
+
+    /// Returns `true` if this is `SharedError::Evicted`.
+    pub fn is_evicted(&self) -> bool {
+        matches!(*self, SharedError::Evicted)
+    }
+
+    /// Returns the inner error if this is `SharedError::Inner`.
+    pub fn into_inner(self) -> Option<E> {
+        match self {
+            SharedError::Inner(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+
+impl<E> From<E> for SharedError<E> {
+    fn from(e: E) -> SharedError<E> {
+        SharedError::Inner(e)
+    }
+}
+
+
+impl<E: fmt::Display> fmt::Display for SharedError<E> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SharedError::Inner(ref e) => e.fmt(fmt),
+            SharedError::Poisoned => write!(fmt, "other thread panicked while using shared source"),
+            SharedError::Evicted => write!(fmt, "evicted from shared source for falling behind"),
+        }
+    }
+}
+
+
+impl<E: Error + 'static> Error for SharedError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SharedError::Inner(ref e) => Some(e),
+            SharedError::Poisoned | SharedError::Evicted => None,
+        }
+    }
+}
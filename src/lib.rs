@@ -1,10 +1,26 @@
+//! An extension of `futures`.
+//!
+//! # Features
+//!
+//! - `net`: Serves publish subscribe channels over a local socket by
+//!   `unsync::pubsub::net` module. It depends on `bytes`, `tokio-io` and `tokio-codec`, so it is
+//!   disabled by default.
+
 #[macro_use]
 extern crate futures;
+#[cfg(feature = "net")]
+extern crate bytes;
+#[cfg(feature = "net")]
+extern crate tokio_io;
+#[cfg(feature = "net")]
+extern crate tokio_codec;
 
 pub mod unsync;
 pub mod stream;
 pub mod sink;
 pub mod util;
+pub mod error;
 
 pub use self::stream::StreamExt;
 pub use self::sink::SinkExt;
+pub use self::error::SharedError;
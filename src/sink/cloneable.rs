@@ -1,6 +1,8 @@
 use futures::{Sink, Poll, Async, AsyncSink, StartSend};
 
-use std::sync::{Arc, Mutex};
+use error::SharedError;
+
+use std::sync::{Arc, Mutex, MutexGuard};
 
 
 /// Convert given stream into `Cloneable`.
@@ -28,6 +30,11 @@ struct Shared<S: Sink> {
 /// A cloneable stream being created by `into_cloneable` function.
 /// You can `clone` this stream as you want.
 /// Each cloned stream is also cloneable.
+///
+/// # Poisoning
+///
+/// If another thread panics while it uses original sink, every operation returns
+/// `SharedError::Poisoned`.
 pub struct Cloneable<S: Sink> {
     shared: Arc<Mutex<Shared<S>>>,
     closed: bool,
@@ -38,48 +45,65 @@ pub struct Cloneable<S: Sink> {
 
 impl<S: Sink> Sink for Cloneable<S> {
     type SinkItem = S::SinkItem;
-    type SinkError = S::SinkError;
+    type SinkError = SharedError<S::SinkError>;
 
-    fn start_send(&mut self, msg: S::SinkItem) -> StartSend<S::SinkItem, S::SinkError> {
+    fn start_send(
+        &mut self,
+        msg: S::SinkItem,
+    ) -> StartSend<S::SinkItem, SharedError<S::SinkError>> {
         if self.closed {
             panic!("A sink which is already closed are used !!");
         }
 
-        let mut shared = self.shared.lock().unwrap();
+        let mut shared = match self.shared.lock() {
+            Ok(shared) => shared,
+            Err(_poisoned) => return Err(SharedError::Poisoned),
+        };
 
         match shared.sink.start_send(msg) {
             Ok(AsyncSink::Ready) => Ok(AsyncSink::Ready),
             Ok(AsyncSink::NotReady(msg)) => Ok(AsyncSink::NotReady(msg)),
-            Err(e) => Err(e),
+            Err(e) => Err(SharedError::Inner(e)),
         }
     }
 
 
-    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+    fn poll_complete(&mut self) -> Poll<(), SharedError<S::SinkError>> {
         if self.closed {
             panic!("A sink which is already closed are used !!");
         }
 
-        let mut shared = self.shared.lock().unwrap();
+        let mut shared = match self.shared.lock() {
+            Ok(shared) => shared,
+            Err(_poisoned) => return Err(SharedError::Poisoned),
+        };
 
         match shared.sink.poll_complete() {
             Ok(Async::Ready(())) => Ok(Async::Ready(())),
             Ok(Async::NotReady) => Ok(Async::NotReady),
-            Err(e) => Err(e),
+            Err(e) => Err(SharedError::Inner(e)),
         }
     }
 
 
-    fn close(&mut self) -> Poll<(), S::SinkError> {
+    fn close(&mut self) -> Poll<(), SharedError<S::SinkError>> {
         if self.closed {
             panic!("A sink which is already closed are used !!");
         }
 
-        let mut shared = self.shared.lock().unwrap();
+        let mut shared = match self.shared.lock() {
+            Ok(shared) => shared,
+            Err(poisoned) => {
+                // Release this sender anyway.
+                self.closed = true;
+                poisoned.into_inner().sender_count -= 1;
+                return Err(SharedError::Poisoned);
+            }
+        };
         shared.sender_count -= 1;
         self.closed = true;
         if shared.sender_count == 0 {
-            shared.sink.close()
+            shared.sink.close().map_err(SharedError::Inner)
         } else {
             Ok(Async::Ready(()))
         }
@@ -95,8 +119,7 @@ impl<S: Sink> Clone for Cloneable<S> {
             closed: false,
         };
 
-        let mut shared = self.shared.lock().unwrap();
-        shared.sender_count += 1;
+        lock_ignoring_poison(&self.shared).sender_count += 1;
 
         cloned
     }
@@ -106,13 +129,22 @@ impl<S: Sink> Clone for Cloneable<S> {
 impl<S: Sink> Drop for Cloneable<S> {
     fn drop(&mut self) {
         if !self.closed {
-            let mut shared = self.shared.lock().unwrap();
-            shared.sender_count -= 1;
+            // Never panic in `drop`.
+            lock_ignoring_poison(&self.shared).sender_count -= 1;
         }
     }
 }
 
 
+/// `sender_count` is always consistent because it is never updated while original sink is used.
+fn lock_ignoring_poison<S: Sink>(shared: &Mutex<Shared<S>>) -> MutexGuard<'_, Shared<S>> {
+    match shared.lock() {
+        Ok(shared) => shared,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+
 impl<S: Sink> ::std::fmt::Debug for Cloneable<S> {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
         write!(f, "ex_futures::sink::Cloneable(..)")
@@ -1,56 +1,608 @@
 use futures::{Stream, Poll, Async};
 use futures::task::{self, Task};
 
-use std::sync::{Arc, Weak, Mutex, TryLockError};
-use std::collections::VecDeque;
+use error::SharedError;
+use super::DEFAULT_BUDGET;
+
+use std::sync::{Arc, Weak, Mutex, MutexGuard, TryLockError};
+use std::collections::{VecDeque, HashMap};
+use std::time::{Duration, Instant};
 
 
 /// Convert given stream into `Cloneable`.
 /// `Cloneable` is able to be cloned.
 pub fn cloneable<S: Stream>(stream: S) -> Cloneable<S> {
-    let queue = Arc::new(Mutex::new(VecDeque::new()));
+    new(stream, None, 0, None)
+}
+
 
-    let receivers = vec![Arc::downgrade(&queue)];
+/// Convert given stream into `Cloneable` whose queues have limited capacity.
+pub fn cloneable_bounded<S: Stream>(stream: S, capacity: usize) -> Cloneable<S> {
+    assert!(capacity > 0, "capacity of cloneable stream must be positive");
+    new(stream, Some(capacity), 0, None)
+}
 
-    let (tx, rx) = ::std::sync::mpsc::channel();
 
-    let shared = Shared {
-        stream: stream,
-        receivers: receivers,
-        block_receiver: rx,
-    };
+/// Convert given stream into `Cloneable` which keeps every item for clones created later.
+pub fn cached<S: Stream>(stream: S) -> Cloneable<S> {
+    new(stream, None, usize::MAX, None)
+}
+
+
+/// Convert given stream into `Cloneable` which keeps last `n` items for clones created later.
+pub fn replayable<S: Stream>(stream: S, n: usize) -> Cloneable<S> {
+    new(stream, None, n, None)
+}
+
+
+/// Convert given stream into `Cloneable` which evicts clones falling behind according to
+/// `eviction`.
+pub fn cloneable_evicting<S: Stream>(stream: S, eviction: Eviction) -> Cloneable<S> {
+    if let Eviction::Backlog(n) = eviction {
+        assert!(n > 0, "backlog of cloneable stream must be positive");
+    }
+    new(stream, None, 0, Some(eviction))
+}
+
+
+/// Convert given stream into `Connectable`.
+/// Original stream is not polled until `Connectable::connect` is called.
+pub fn publish<S: Stream>(stream: S) -> Connectable<S> {
+    let buffer = new_buffer(None, 0, None, Some(0));
+
+    Connectable {
+        buffer: Arc::new(Mutex::new(buffer)),
+        shared: Arc::new(Mutex::new(Shared { stream: Some(stream) })),
+    }
+}
+
+
+fn new<S: Stream>(
+    stream: S,
+    capacity: Option<usize>,
+    history: usize,
+    eviction: Option<Eviction>,
+) -> Cloneable<S> {
+    let mut buffer = new_buffer(capacity, history, eviction, None);
+    // Nothing is released because `buffer` is empty.
+    let (id, _) = buffer.add_reader(None);
 
     Cloneable {
-        queue: queue,
-        shared: Arc::new(Mutex::new(shared)),
-        block_notifier: tx,
+        id: id,
+        buffer: Arc::new(Mutex::new(buffer)),
+        shared: Arc::new(Mutex::new(Shared { stream: Some(stream) })),
+        finished: false,
+        budget: DEFAULT_BUDGET,
     }
 }
 
 
+fn new_buffer<T, E>(
+    capacity: Option<usize>,
+    history: usize,
+    eviction: Option<Eviction>,
+    connections: Option<usize>,
+) -> Buffer<T, E> {
+    Buffer {
+        slots: VecDeque::new(),
+        head: 0,
+        released: 0,
+        history: history,
+        history_start: 0,
+        history_items: 0,
+        readers: HashMap::new(),
+        lossy: Vec::new(),
+        parked: Vec::new(),
+        next_id: 0,
+        capacity: capacity,
+        blocked: Vec::new(),
+        eviction: eviction,
+        connections: connections,
+        has_connectable: connections.is_some(),
+        budget: DEFAULT_BUDGET,
+        dispatched: 0,
+        terminated: false,
+    }
+}
+
+
+/// A policy to detach clones which fall behind others.
+///
+/// An evicted clone returns `SharedError::Evicted` at its next poll and then finishes. Other
+/// clones are not affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// Evicts a clone when it has more than given number of unread items.
+    Backlog(usize),
+
+    /// Evicts a clone when its oldest unread item has been waiting longer than given duration.
+    ///
+    /// Age is only checked when a new item is pushed. So a clone is not evicted while original
+    /// stream produces nothing.
+    Age(Duration),
+}
+
+
 struct Shared<S: Stream> {
-    stream: S,
-    receivers: Vec<Weak<Queue<S>>>,
-    block_receiver: ::std::sync::mpsc::Receiver<Task>,
+    // `None` after original stream finishes or every clone is gone.
+    stream: Option<S>,
+}
+
+
+/// Absolute position of an item in the original stream.
+type Index = u64;
+
+type ReaderId = u64;
+
+/// An item, an error or the end of original stream.
+type Msg<T, E> = Result<Option<T>, E>;
+
+/// Items removed from `Buffer`. They must be dropped after `Buffer` is unlocked, because an item
+/// may hold a clone of the same stream.
+type Released<T, E> = Vec<Msg<T, E>>;
+
+
+/// Every item of original stream is stored only once in `Buffer`.
+/// Each clone has its own cursor pointing at the next item to read. An item is released when every
+/// clone has read it. Last `history` items are kept for clones created later, with errors and
+/// the end following them.
+struct Buffer<T, E> {
+    slots: VecDeque<Slot<T, E>>,
+    // Index of `slots[0]`.
+    head: Index,
+    // Number of released items at the front of `slots`.
+    released: usize,
+    history: usize,
+    // Index of the first slot kept for clones created later, unless `history` is 0.
+    history_start: Index,
+    // Number of items from `history_start`. Errors and the end are not counted.
+    history_items: usize,
+    // Living clones which are not evicted.
+    readers: HashMap<ReaderId, Reader>,
+    // Lossy clones in `readers`. Only they are checked when an item is pushed.
+    lossy: Vec<ReaderId>,
+    // Clones which may have parked tasks. Only they are checked when an item is pushed.
+    parked: Vec<ReaderId>,
+    next_id: ReaderId,
+    capacity: Option<usize>,
+    // Tasks waiting for `slots` to have space or for a connection.
+    blocked: Vec<Task>,
+    eviction: Option<Eviction>,
+    // Number of living `Connection`s. `None` unless this is created by `publish` function.
+    connections: Option<usize>,
+    // Whether `Connectable` is living.
+    has_connectable: bool,
+    // Budget of clones created by `WeakCloneable::upgrade` and `Connectable::subscribe`. This is
+    // the budget which is set last.
+    budget: usize,
+    // Number of items which original stream has produced.
+    dispatched: u64,
+    terminated: bool,
+}
+
+
+struct Slot<T, E> {
+    msg: Result<Option<T>, E>,
+    // Number of clones which have not read this item yet.
+    remaining: usize,
+    // Only recorded when clones are evicted by age.
+    pushed_at: Option<Instant>,
+}
+
+
+struct Reader {
+    // Index of the next item to read.
+    cursor: Index,
+    // Task waiting for a new item.
+    task: Option<Task>,
+    // Whether this clone is in `Buffer::parked`.
+    parked: bool,
+    // Capacity of a lossy clone.
+    lossy: Option<usize>,
+    // Number of items which this lossy clone has dropped.
+    dropped: u64,
+}
+
+
+impl Reader {
+    fn new(cursor: Index, lossy: Option<usize>) -> Reader {
+        Reader {
+            cursor: cursor,
+            task: None,
+            parked: false,
+            lossy: lossy,
+            dropped: 0,
+        }
+    }
+}
+
+
+impl<T: Clone, E: Clone> Buffer<T, E> {
+    /// Reads the next item of a clone, and returns items which are released by reading it.
+    fn read(&mut self, id: ReaderId) -> (Option<Msg<T, E>>, Released<T, E>) {
+        let offset = match self.readers.get(&id) {
+            Some(reader) => (reader.cursor - self.head) as usize,
+            None => return (None, Vec::new()),
+        };
+        if offset == self.slots.len() {
+            return (None, Vec::new());
+        }
+
+        let is_last = offset == 0 && self.slots.front().map(|s| s.remaining == 1) == Some(true);
+
+        let (msg, released) = if is_last && self.history == 0 {
+            // This is the last clone reading the item. So we can take it without cloning.
+            let msg = self.pop_slot();
+            self.notify_blocked();
+            (msg, Vec::new())
+        } else {
+            let msg = {
+                let slot = &mut self.slots[offset];
+                // Clone it before counting it as read, so that `Buffer` keeps consistent even if
+                // `clone` panics.
+                let msg = slot.msg.clone();
+                slot.remaining -= 1;
+                msg
+            };
+            (msg, self.release())
+        };
+
+        if let Some(reader) = self.readers.get_mut(&id) {
+            reader.cursor += 1;
+        }
+        // This clone has space now, even if lossy clones keep the item.
+        self.notify_blocked();
+        (Some(msg), released)
+    }
+}
+
+
+impl<T, E> Buffer<T, E> {
+    fn tail(&self) -> Index {
+        self.head + self.slots.len() as Index
+    }
+
+    fn push(&mut self, msg: Result<Option<T>, E>) -> Released<T, E> {
+        let is_item = match msg {
+            Ok(Some(_)) => {
+                self.dispatched += 1;
+                true
+            }
+            Ok(None) => {
+                self.terminated = true;
+                false
+            }
+            Err(_) => false,
+        };
+
+        let pushed_at = match self.eviction {
+            Some(Eviction::Age(_)) => Some(Instant::now()),
+            _ => None,
+        };
+
+        let slot = Slot {
+            msg: msg,
+            remaining: self.readers.len(),
+            pushed_at: pushed_at,
+        };
+        self.slots.push_back(slot);
+
+        if is_item {
+            self.keep();
+        }
+        self.drop_overflow()
+    }
+
+    /// Moves the start of history after an item is pushed. History starts at the `history`-th
+    /// last item, or at the front if there are fewer items. Errors and the end are kept with the
+    /// items around them, but they are not counted.
+    fn keep(&mut self) {
+        if self.history == 0 {
+            return;
+        }
+
+        self.history_items += 1;
+        loop {
+            let slot = &self.slots[(self.history_start - self.head) as usize];
+            let is_item = matches!(slot.msg, Ok(Some(_)));
+            let outside = if is_item {
+                self.history_items > self.history
+            } else {
+                self.history_items >= self.history
+            };
+            if !outside {
+                break;
+            }
+            self.history_start += 1;
+            if is_item {
+                self.history_items -= 1;
+            }
+        }
+    }
+
+    /// Makes every lossy clone skip its oldest items while it is full.
+    fn drop_overflow(&mut self) -> Released<T, E> {
+        let tail = self.tail();
+        let head = self.head;
+        let slots = &mut self.slots;
+
+        for id in self.lossy.iter() {
+            let reader = self.readers.get_mut(id).unwrap();
+            if let Some(capacity) = reader.lossy {
+                while tail - reader.cursor > capacity as Index {
+                    slots[(reader.cursor - head) as usize].remaining -= 1;
+                    reader.cursor += 1;
+                    reader.dropped += 1;
+                }
+            }
+        }
+
+        self.release()
+    }
+
+    /// Removes clones which fall behind according to eviction policy, and returns their tasks
+    /// and released items.
+    fn evict(&mut self) -> (Vec<Task>, Released<T, E>) {
+        if self.eviction.is_none() {
+            return (Vec::new(), Vec::new());
+        }
+
+        let evicted: Vec<ReaderId> = {
+            let tail = self.tail();
+            let head = self.head;
+            let slots = &self.slots;
+            let is_behind = |reader: &Reader| match self.eviction {
+                Some(Eviction::Backlog(n)) => tail - reader.cursor > n as Index,
+                Some(Eviction::Age(age)) => {
+                    slots
+                        .get((reader.cursor - head) as usize)
+                        .and_then(|slot| slot.pushed_at)
+                        .map(|pushed_at| pushed_at.elapsed() > age) == Some(true)
+                }
+                None => false,
+            };
+
+            self.readers
+                .iter()
+                .filter(|&(_, reader)| is_behind(reader))
+                .map(|(id, _)| *id)
+                .collect()
+        };
+
+        let mut tasks = Vec::new();
+        let mut released = Vec::new();
+        for id in evicted {
+            let (task, items) = self.remove_reader(id);
+            tasks.extend(task);
+            released.extend(items);
+        }
+        (tasks, released)
+    }
+
+    fn queue_len(&self, id: ReaderId) -> usize {
+        match self.readers.get(&id) {
+            Some(reader) => (self.tail() - reader.cursor) as usize,
+            None => 0,
+        }
+    }
+
+    /// Registers current task to be notified when a new item arrives.
+    fn park(&mut self, id: ReaderId) {
+        if let Some(reader) = self.readers.get_mut(&id) {
+            reader.task = Some(task::current());
+            if !reader.parked {
+                reader.parked = true;
+                self.parked.push(id);
+            }
+        }
+    }
+
+    fn unpark(&mut self, id: ReaderId) {
+        if let Some(reader) = self.readers.get_mut(&id) {
+            reader.task = None;
+        }
+    }
+
+    /// Takes tasks of every parked clone.
+    fn take_parked(&mut self) -> Vec<Task> {
+        let mut tasks = Vec::new();
+        for id in self.parked.drain(..) {
+            if let Some(reader) = self.readers.get_mut(&id) {
+                reader.parked = false;
+                tasks.extend(reader.task.take());
+            }
+        }
+        tasks
+    }
+
+    fn is_full(&self) -> bool {
+        match self.capacity {
+            Some(capacity) => {
+                // Lossy clones never throttle original stream.
+                let tail = self.tail();
+                self.readers.values().any(|reader| {
+                    reader.lossy.is_none() && tail - reader.cursor >= capacity as Index
+                })
+            }
+            None => false,
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connections.map(|n| n > 0).unwrap_or(true)
+    }
+
+    /// Returns `true` while somebody is able to read items from original stream.
+    fn is_alive(&self) -> bool {
+        !self.readers.is_empty() || self.has_connectable
+    }
+
+    /// Registers a new clone which reads last `history` items at first.
+    fn add_reader(&mut self, lossy: Option<usize>) -> (ReaderId, Released<T, E>) {
+        let id = self.new_id();
+
+        let offset = self.history_offset();
+        for slot in self.slots.iter_mut().skip(offset) {
+            slot.remaining += 1;
+        }
+        self.released = ::std::cmp::min(self.released, offset);
+
+        let cursor = self.head + offset as Index;
+        self.readers.insert(id, Reader::new(cursor, lossy));
+        if lossy.is_some() {
+            self.lossy.push(id);
+        }
+        (id, self.drop_overflow())
+    }
+
+    /// Returns an id which is not registered as a reader. A clone having it never reads items.
+    fn new_id(&mut self) -> ReaderId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Registers a clone of `id` unless it is already detached, because an evicted or poisoned
+    /// clone must not keep items for its clones which never read them.
+    fn add_clone(
+        &mut self,
+        id: ReaderId,
+        finished: bool,
+        lossy: Option<usize>,
+    ) -> (ReaderId, Released<T, E>) {
+        if finished || !self.readers.contains_key(&id) {
+            return (self.new_id(), Vec::new());
+        }
+        self.add_reader(lossy)
+    }
+
+    /// Removes a clone and returns its parked task and released items. This does nothing if it
+    /// is already evicted.
+    fn remove_reader(&mut self, id: ReaderId) -> (Option<Task>, Released<T, E>) {
+        let reader = match self.readers.remove(&id) {
+            Some(reader) => reader,
+            None => return (None, Vec::new()),
+        };
+        if reader.lossy.is_some() {
+            self.lossy.retain(|lossy| *lossy != id);
+        }
+
+        let offset = (reader.cursor - self.head) as usize;
+        for slot in self.slots.iter_mut().skip(offset) {
+            slot.remaining -= 1;
+        }
+
+        (reader.task, self.release())
+    }
+
+    /// Releases items which every clone has read, and removes released items which are not
+    /// needed as history. Removed items are returned.
+    fn release(&mut self) -> Released<T, E> {
+        let mut released_any = false;
+        while self.slots.get(self.released).map(|s| s.remaining == 0) == Some(true) {
+            self.released += 1;
+            released_any = true;
+        }
+
+        let mut removed = Vec::new();
+        while self.released > 0 && !self.is_history(0) {
+            removed.push(self.pop_slot());
+            self.released -= 1;
+        }
+
+        if released_any {
+            self.notify_blocked();
+        }
+        removed
+    }
+
+    fn notify_blocked(&mut self) {
+        for task in self.blocked.drain(..) {
+            task.notify();
+        }
+    }
+
+    fn pop_slot(&mut self) -> Result<Option<T>, E> {
+        let slot = self.slots.pop_front().unwrap();
+        self.head += 1;
+        slot.msg
+    }
+
+    /// Returns `true` if the slot at `offset` is kept for clones created later.
+    fn is_history(&self, offset: usize) -> bool {
+        offset >= self.history_offset()
+    }
+
+    /// Returns the offset of the first slot kept for clones created later.
+    fn history_offset(&self) -> usize {
+        if self.history == 0 {
+            return self.slots.len();
+        }
+        (self.history_start - self.head) as usize
+    }
+}
+
+
+/// Drops original stream if nobody holds `Shared`.
+fn release_stream<S: Stream>(shared: &Mutex<Shared<S>>) {
+    let stream = match shared.try_lock() {
+        Ok(mut shared) => shared.stream.take(),
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().stream.take(),
+        Err(TryLockError::WouldBlock) => None,
+    };
+    // Original stream may hold a `Connection`, so it is dropped after `Shared` is unlocked.
+    drop(stream);
 }
 
 
-type Queue<S: Stream> = Mutex<VecDeque<Result<Option<S::Item>, S::Error>>>;
+fn lock_buffer<T, E>(buffer: &Mutex<Buffer<T, E>>) -> MutexGuard<'_, Buffer<T, E>> {
+    match buffer.lock() {
+        Ok(buffer) => buffer,
+        // `Buffer` is always consistent because an item is counted as read after it is cloned.
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
 
 
-/// A cloneable stream being created by `unsync_cloneable` function.
+/// A cloneable stream being created by `cloneable` function.
 /// You can `clone` this stream as you want.
 /// Each cloned stream is also cloneable.
 ///
+/// Each item of original stream is stored only once and is cloned when a clone reads it.
+///
+/// If this stream is created by `cached` or `replayable` function, a new clone reads kept items
+/// at first.
+///
 /// If you want to see examples, please have a look at document of `StreamExt` trait.
 ///
-/// # Panic
+/// # Poisoning
+///
+/// If another thread panics while it polls original stream, every clone returns
+/// `SharedError::Poisoned` after it reads the remaining items, and then finishes.
+///
+/// If this stream is created by `cloneable_bounded` function, original stream is not polled
+/// while any queue is full. So the slowest clone throttles others.
+///
+/// A clone created by `clone_lossy` function never throttles original stream. Instead it skips
+/// its oldest item when its queue is full.
+///
+/// If this stream is created by `cloneable_evicting` function, a clone falling behind others is
+/// detached instead. It returns `SharedError::Evicted` and then finishes. Clones of a detached
+/// clone are detached as well, so they never keep items.
+///
+/// If this stream is created by `Connectable::subscribe` function, original stream is polled
+/// only while it is connected.
 ///
-/// This stream will panic when another `Cloneable` stream panics.
+/// Original stream is dropped as soon as it finishes or every clone which is not evicted is
+/// dropped.
 pub struct Cloneable<S: Stream> {
-    queue: Arc<Queue<S>>,
+    id: ReaderId,
+    buffer: Arc<Mutex<Buffer<S::Item, S::Error>>>,
     shared: Arc<Mutex<Shared<S>>>,
-    block_notifier: ::std::sync::mpsc::Sender<Task>,
+    // Set after this clone returns `SharedError::Poisoned` or `SharedError::Evicted`.
+    finished: bool,
+    // Maximum number of times one poll of this clone polls original stream.
+    budget: usize,
 }
 
 
@@ -62,77 +614,244 @@ where
     S::Error: Clone,
 {
     type Item = S::Item;
-    type Error = S::Error;
-
-    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
-        // Check self queue
-        let msg_res = match self.queue.lock() {
-            Ok(mut queue) => queue.pop_front(),
-            Err(_poisoned) => {
-                // Currently we just panic thread if mutex is poisoned.
-                panic!("Other thread seems to panic during processing cloned stream.")
-            }
-        };
+    type Error = SharedError<S::Error>;
 
-        match msg_res {
-            Some(Ok(Some(msg))) => return Ok(Async::Ready(Some(msg))),
-            Some(Ok(None)) => return Ok(Async::Ready(None)),
-            Some(Err(e)) => return Err(e),
-            None => (),
+    fn poll(&mut self) -> Poll<Option<S::Item>, SharedError<S::Error>> {
+        if self.finished {
+            return Ok(Async::Ready(None));
+        }
+
+        match self.poll_shared() {
+            Err(SharedError::Poisoned) => {
+                // Finish this clone and let others know it.
+                self.finished = true;
+                self.notify_parked();
+                Err(SharedError::Poisoned)
+            }
+            Err(SharedError::Evicted) => {
+                self.finished = true;
+                Err(SharedError::Evicted)
+            }
+            res => res,
         }
+    }
+}
+
+
 
-        {
-            // Try to get `Shared`.
-            let mut shared = match self.shared.try_lock() {
-                Ok(shared) => shared,
-                Err(TryLockError::WouldBlock) => {
-                    self.block_notifier.send(task::current()).unwrap(); // Never be disconnected.
-                    return Ok(Async::NotReady);
+impl<S> Cloneable<S>
+where
+    S: Stream,
+    S::Item: Clone,
+    S::Error: Clone,
+{
+    fn poll_shared(&mut self) -> Poll<Option<S::Item>, SharedError<S::Error>> {
+        let mut polled = 0;
+
+        loop {
+            // Check buffer
+            let (msg_res, released) = {
+                let mut buffer = lock_buffer(&self.buffer);
+                if !buffer.readers.contains_key(&self.id) {
+                    return Err(SharedError::Evicted);
                 }
-                Err(TryLockError::Poisoned(_poisoned)) => {
-                    // Currently we just panic thread if mutex is poisoned.
-                    panic!("Other thread seems to panic during processing cloned stream.")
+                let (msg, released) = buffer.read(self.id);
+                if msg.is_none() {
+                    // Register current task before trying to get `Shared`. So if other clone
+                    // holds `Shared` now, it will surely notify us after it pushes a new item.
+                    buffer.park(self.id);
                 }
+                (msg, released)
             };
+            drop(released);
 
-            let poll = shared.stream.poll();
+            match msg_res {
+                Some(Ok(Some(msg))) => return Ok(Async::Ready(Some(msg))),
+                Some(Ok(None)) => return Ok(Async::Ready(None)),
+                Some(Err(e)) => return Err(SharedError::Inner(e)),
+                None => (),
+            }
 
-            let msg = match poll {
-                Err(e) => Err(e.clone()),
-                Ok(Async::Ready(Some(msg))) => Ok(Some(msg)),
-                Ok(Async::Ready(None)) => Ok(None),
-                Ok(Async::NotReady) => {
-                    return Ok(Async::NotReady);
-                }
-            };
+            if polled == self.budget {
+                // Original stream keeps producing items without giving one to this clone.
+                // Yield to other tasks, and poll it again soon.
+                task::current().notify();
+                return Ok(Async::NotReady);
+            }
+            polled += 1;
 
-            // Do not remove outdated receivers here because of performance.
-            // We do it at `Cloneable::clone` function.
-            for rx in shared.receivers.iter().filter_map(Weak::upgrade) {
-                match rx.lock() {
-                    Ok(mut queue) => queue.push_back(msg.clone()),
-                    Err(_poisoned) => {
-                        // Currently we just panic thread if mutex is poisoned.
-                        panic!("Other thread seems to panic during processing cloned stream.")
+            {
+                // Try to get `Shared`.
+                let mut shared = match self.shared.try_lock() {
+                    Ok(shared) => shared,
+                    Err(TryLockError::WouldBlock) => {
+                        // Current task is already parked.
+                        return Ok(Async::NotReady);
+                    }
+                    Err(TryLockError::Poisoned(_poisoned)) => return Err(SharedError::Poisoned),
+                };
+
+                // Stop polling original stream while buffer is full or it is not connected.
+                // Only the holder of `Shared` pushes items, so buffer never gets full before we
+                // push.
+                {
+                    let mut buffer = lock_buffer(&self.buffer);
+                    if buffer.is_full() || !buffer.is_connected() {
+                        buffer.blocked.push(task::current());
+                        return Ok(Async::NotReady);
                     }
                 }
+
+                let poll = match shared.stream.as_mut() {
+                    Some(stream) => stream.poll(),
+                    // Original stream has already finished and this clone was created after that.
+                    // Other clones may be parked on us, and they never get a new item.
+                    None => {
+                        let tasks = {
+                            let mut buffer = lock_buffer(&self.buffer);
+                            buffer.unpark(self.id);
+                            buffer.take_parked()
+                        };
+                        drop(shared);
+                        tasks.iter().for_each(|task| task.notify());
+                        return Ok(Async::Ready(None));
+                    }
+                };
+
+                let mut finished = None;
+                let msg = match poll {
+                    Err(e) => Err(e),
+                    Ok(Async::Ready(Some(msg))) => Ok(Some(msg)),
+                    Ok(Async::Ready(None)) => {
+                        // Release resources of original stream right now, after `Shared` is
+                        // unlocked.
+                        finished = shared.stream.take();
+                        Ok(None)
+                    }
+                    Ok(Async::NotReady) => {
+                        return Ok(Async::NotReady);
+                    }
+                };
+
+                let (tasks, released) = {
+                    let mut buffer = lock_buffer(&self.buffer);
+                    let mut released = buffer.push(msg);
+                    buffer.unpark(self.id); // We are going to read it right now.
+                    let (mut tasks, evicted) = buffer.evict();
+                    released.extend(evicted);
+                    tasks.extend(buffer.take_parked());
+                    (tasks, released)
+                };
+
+                drop(shared); // We need not to do this but this is more explicitly.
+                drop(finished);
+                drop(released);
+
+                tasks.iter().for_each(|task| task.notify());
             }
+        }
+    }
+}
 
-            // Probably there is better design.
-            let tasks = {
-                let mut vec = Vec::new();
-                while let Ok(task) = shared.block_receiver.try_recv() {
-                    vec.push(task);
-                }
-                vec
-            };
 
-            drop(shared); // We need not to do this but this is more explicitly.
 
-            tasks.iter().for_each(|task| task.notify());
+impl<S: Stream> Cloneable<S> {
+    /// Returns the number of living clones including this one.
+    pub fn clone_count(&self) -> usize {
+        lock_buffer(&self.buffer).readers.len()
+    }
+
+    /// Returns the number of items which this clone has not read yet.
+    pub fn queue_len(&self) -> usize {
+        lock_buffer(&self.buffer).queue_len(self.id)
+    }
+
+    /// Returns `true` if original stream has finished.
+    pub fn is_terminated(&self) -> bool {
+        lock_buffer(&self.buffer).terminated
+    }
+
+    /// Returns the number of items which original stream has produced.
+    pub fn dispatched(&self) -> u64 {
+        lock_buffer(&self.buffer).dispatched
+    }
+
+    /// Returns `true` if original stream never produces a new item because it has finished or
+    /// another thread panicked while polling it.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.is_terminated() || self.shared.is_poisoned()
+    }
+
+    /// Returns `true` if this clone is created by `clone_lossy` function.
+    pub fn is_lossy(&self) -> bool {
+        self.reader(|reader| reader.lossy.is_some())
+    }
+
+    /// Returns the number of items which this lossy clone has dropped.
+    pub fn dropped(&self) -> u64 {
+        self.reader(|reader| reader.dropped)
+    }
+
+    /// Creates a lossy clone whose queue holds at most `capacity` items.
+    /// It never throttles original stream, and skips its oldest item when its queue is full.
+    /// Clones of a lossy clone are also lossy.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `capacity` is 0.
+    pub fn clone_lossy(&self, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity of lossy clone must be positive");
+        let (id, released) =
+            lock_buffer(&self.buffer).add_clone(self.id, self.finished, Some(capacity));
+        drop(released);
+
+        Cloneable {
+            id: id,
+            buffer: self.buffer.clone(),
+            shared: self.shared.clone(),
+            finished: self.finished,
+            budget: self.budget,
+        }
+    }
+
+    fn reader<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&Reader) -> T,
+        T: Default,
+    {
+        lock_buffer(&self.buffer)
+            .readers
+            .get(&self.id)
+            .map(f)
+            .unwrap_or_default()
+    }
+
+    /// Sets the maximum number of times one poll of this clone polls original stream without
+    /// getting an item. After that, the poll yields to other tasks by notifying current task and
+    /// returning `NotReady`. The default is 32. Clones of this clone and clones created later by
+    /// `WeakCloneable::upgrade` or `Connectable::subscribe` inherit it.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `budget` is 0.
+    pub fn set_budget(&mut self, budget: usize) {
+        assert!(budget > 0, "budget must be positive");
+        self.budget = budget;
+        lock_buffer(&self.buffer).budget = budget;
+    }
+
+    /// Creates a weak handle which does not consume items.
+    /// You can create a new clone from it while any clone is living.
+    pub fn downgrade(&self) -> WeakCloneable<S> {
+        WeakCloneable {
+            buffer: Arc::downgrade(&self.buffer),
+            shared: Arc::downgrade(&self.shared),
         }
+    }
 
-        self.poll()
+    fn notify_parked(&self) {
+        let tasks = lock_buffer(&self.buffer).take_parked();
+        tasks.iter().for_each(|task| task.notify());
     }
 }
 
@@ -140,32 +859,234 @@ where
 
 impl<S: Stream> Clone for Cloneable<S> {
     fn clone(&self) -> Self {
-        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let (id, released) = {
+            let mut buffer = lock_buffer(&self.buffer);
+            let lossy = buffer.readers.get(&self.id).and_then(|reader| reader.lossy);
+            buffer.add_clone(self.id, self.finished, lossy)
+        };
+        drop(released);
+
+        Cloneable {
+            id: id,
+            buffer: self.buffer.clone(),
+            shared: self.shared.clone(),
+            finished: self.finished,
+            budget: self.budget,
+        }
+    }
+}
+
+
+
+impl<S: Stream> Drop for Cloneable<S> {
+    fn drop(&mut self) {
+        let (tasks, is_last, released) = {
+            let mut buffer = lock_buffer(&self.buffer);
+            let (_, released) = buffer.remove_reader(self.id);
 
-        let mut shared = match self.shared.lock() {
-            Ok(shared) => shared,
-            Err(_poisoned) => {
-                // Currently we just panic thread if mutex is poisoned.
-                panic!("Other thread seems to panic during processing cloned stream.")
+            // This clone may be the one which original stream will notify. So we notify others
+            // instead.
+            (buffer.take_parked(), !buffer.is_alive(), released)
+        };
+
+        // Items may hold clones of this stream.
+        drop(released);
+        tasks.iter().for_each(|task| task.notify());
+
+        if is_last {
+            // Only evicted clones remain, and they never poll original stream again.
+            // Nobody else holds `Shared` because only living clones lock it.
+            release_stream(&self.shared);
+        }
+    }
+}
+
+
+
+impl<S: Stream> ::std::fmt::Debug for Cloneable<S> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        let buffer = lock_buffer(&self.buffer);
+        f.debug_struct("Cloneable")
+            .field("clone_count", &buffer.readers.len())
+            .field("queue_len", &buffer.queue_len(self.id))
+            .field("terminated", &buffer.terminated)
+            .field("dispatched", &buffer.dispatched)
+            .field("budget", &self.budget)
+            .finish()
+    }
+}
+
+
+
+/// A weak handle of `Cloneable` stream being created by `Cloneable::downgrade` function.
+///
+/// This handle does not read items, so items are never accumulated for it. And it does not keep
+/// original stream alive.
+pub struct WeakCloneable<S: Stream> {
+    buffer: Weak<Mutex<Buffer<S::Item, S::Error>>>,
+    shared: Weak<Mutex<Shared<S>>>,
+}
+
+
+
+impl<S: Stream> WeakCloneable<S> {
+    /// Creates a new clone if any clone which is not evicted is living.
+    /// The new clone reads items arriving after now, or kept items if the stream is created by
+    /// `cached` or `replayable` function.
+    pub fn upgrade(&self) -> Option<Cloneable<S>> {
+        let buffer = self.buffer.upgrade()?;
+        let shared = self.shared.upgrade()?;
+
+        let (id, budget, released) = {
+            let mut buffer = lock_buffer(&buffer);
+            if !buffer.is_alive() {
+                // Original stream is already dropped.
+                return None;
             }
+            let (id, released) = buffer.add_reader(None);
+            (id, buffer.budget, released)
         };
+        drop(released);
+
+        Some(Cloneable {
+            id: id,
+            buffer: buffer,
+            shared: shared,
+            finished: false,
+            budget: budget,
+        })
+    }
+}
+
+
+
+impl<S: Stream> Clone for WeakCloneable<S> {
+    fn clone(&self) -> Self {
+        WeakCloneable {
+            buffer: self.buffer.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+
+
+impl<S: Stream> ::std::fmt::Debug for WeakCloneable<S> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "WeakCloneable(..)")
+    }
+}
+
 
-        shared.receivers.retain(|weak| weak.upgrade().is_some()); // Remove outdated receivers.
-        shared.receivers.push(Arc::downgrade(&queue));
-        drop(shared);
+
+/// A stream source being created by `publish` function.
+///
+/// You can create any number of clones by `subscribe` function. Original stream is not polled
+/// until `connect` is called, so every clone created before that reads every item.
+pub struct Connectable<S: Stream> {
+    buffer: Arc<Mutex<Buffer<S::Item, S::Error>>>,
+    shared: Arc<Mutex<Shared<S>>>,
+}
+
+
+
+impl<S: Stream> Connectable<S> {
+    /// Creates a new clone which reads items arriving after now.
+    pub fn subscribe(&self) -> Cloneable<S> {
+        let (id, budget, released) = {
+            let mut buffer = lock_buffer(&self.buffer);
+            let (id, released) = buffer.add_reader(None);
+            (id, buffer.budget, released)
+        };
+        drop(released);
 
         Cloneable {
-            queue: queue,
+            id: id,
+            buffer: self.buffer.clone(),
             shared: self.shared.clone(),
-            block_notifier: self.block_notifier.clone(),
+            finished: false,
+            budget: budget,
+        }
+    }
+
+    /// Sets the budget of clones created after this call. Please have a look at `set_budget` of
+    /// `Cloneable`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `budget` is 0.
+    pub fn set_budget(&self, budget: usize) {
+        assert!(budget > 0, "budget must be positive");
+        lock_buffer(&self.buffer).budget = budget;
+    }
+
+    /// Starts polling original stream. It stops when every returned `Connection` is dropped.
+    pub fn connect(&self) -> Connection<S> {
+        let mut buffer = lock_buffer(&self.buffer);
+        if let Some(ref mut connections) = buffer.connections {
+            *connections += 1;
         }
+        // Clones waiting for a connection.
+        buffer.notify_blocked();
+
+        Connection { buffer: Arc::downgrade(&self.buffer) }
     }
 }
 
 
 
-impl<S: Stream> ::std::fmt::Debug for Cloneable<S> {
+impl<S: Stream> Drop for Connectable<S> {
+    fn drop(&mut self) {
+        let is_last = {
+            let mut buffer = lock_buffer(&self.buffer);
+            buffer.has_connectable = false;
+            !buffer.is_alive()
+        };
+
+        if is_last {
+            release_stream(&self.shared);
+        }
+    }
+}
+
+
+
+impl<S: Stream> ::std::fmt::Debug for Connectable<S> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        let buffer = lock_buffer(&self.buffer);
+        f.debug_struct("Connectable")
+            .field("clone_count", &buffer.readers.len())
+            .field("connected", &buffer.is_connected())
+            .field("terminated", &buffer.terminated)
+            .field("dispatched", &buffer.dispatched)
+            .finish()
+    }
+}
+
+
+
+/// A handle being created by `Connectable::connect` function.
+/// Original stream is not polled after every `Connection` is dropped.
+pub struct Connection<S: Stream> {
+    buffer: Weak<Mutex<Buffer<S::Item, S::Error>>>,
+}
+
+
+
+impl<S: Stream> Drop for Connection<S> {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.upgrade() {
+            if let Some(ref mut connections) = lock_buffer(&buffer).connections {
+                *connections -= 1;
+            }
+        }
+    }
+}
+
+
+
+impl<S: Stream> ::std::fmt::Debug for Connection<S> {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-        write!(f, "Cloneable(..)")
+        write!(f, "Connection(..)")
     }
 }
@@ -1,11 +1,16 @@
 use futures::{Stream, Poll, Async};
+use futures::task;
 
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex, TryLockError};
+use error::SharedError;
+use super::DEFAULT_BUDGET;
+use super::fork_queues::{Queues, Routing};
 
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
 
-pub type LeftFork<S, F> = Fork<S, F>;
-pub type RightFork<S, F> = Fork<S, F>;
+
+pub type LeftFork<S, F, G = Broadcast> = Fork<S, F, G>;
+pub type RightFork<S, F, G = Broadcast> = Fork<S, F, G>;
+pub type ForkWithErrorStream<S, F> = Fork<S, F, ErrorStream>;
 
 
 
@@ -14,30 +19,165 @@ pub fn fork<S, F, T>(stream: S, router: F) -> (LeftFork<S, F>, RightFork<S, F>)
 where
     S: Stream,
     F: FnMut(&S::Item) -> T,
-    Side: From<T>,
+    T: Route<S::Item>,
+{
+    let mut forks = fork_n(stream, 2, router);
+    let right = forks.pop().unwrap();
+    let left = forks.pop().unwrap();
+    (left, right)
+}
+
+
+/// Fork given stream into `n` streams. Please have a look at document of `StreamExt` trait.
+pub fn fork_n<S, F, T>(stream: S, n: usize, router: F) -> Vec<Fork<S, F>>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    new(stream, n, None, router, Broadcast, false)
+}
+
+
+/// Fork given stream into two streams with bounded queues. Please have a look at document of
+/// `StreamExt` trait.
+pub fn fork_bounded<S, F, T>(
+    stream: S,
+    capacity: usize,
+    router: F,
+) -> (LeftFork<S, F>, RightFork<S, F>)
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
 {
+    let mut forks = fork_n_bounded(stream, 2, capacity, router);
+    let right = forks.pop().unwrap();
+    let left = forks.pop().unwrap();
+    (left, right)
+}
+
+
+/// Fork given stream into `n` streams with bounded queues. Please have a look at document of
+/// `StreamExt` trait.
+pub fn fork_n_bounded<S, F, T>(
+    stream: S,
+    n: usize,
+    capacity: usize,
+    router: F,
+) -> Vec<Fork<S, F>>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    assert!(capacity > 0, "capacity of fork must be positive");
+    new(stream, n, Some(capacity), router, Broadcast, false)
+}
+
+
+/// Fork given stream into two streams with an error policy. Please have a look at document of
+/// `StreamExt` trait.
+pub fn fork_with_errors<S, F, T, G>(
+    stream: S,
+    router: F,
+    errors: G,
+) -> (LeftFork<S, F, G>, RightFork<S, F, G>)
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+    G: RouteError<S::Error>,
+{
+    let mut forks = fork_n_with_errors(stream, 2, router, errors);
+    let right = forks.pop().unwrap();
+    let left = forks.pop().unwrap();
+    (left, right)
+}
+
+
+/// Fork given stream into `n` streams with an error policy. Please have a look at document of
+/// `StreamExt` trait.
+pub fn fork_n_with_errors<S, F, T, G>(
+    stream: S,
+    n: usize,
+    router: F,
+    errors: G,
+) -> Vec<Fork<S, F, G>>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+    G: RouteError<S::Error>,
+{
+    new(stream, n, None, router, errors, false)
+}
+
+
+/// Fork given stream into `n` streams and a stream of its errors. Please have a look at document
+/// of `StreamExt` trait.
+pub fn fork_n_with_error_stream<S, F, T>(
+    stream: S,
+    n: usize,
+    router: F,
+) -> (Vec<ForkWithErrorStream<S, F>>, ForkErrors<S, F>)
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    let forks = new(stream, n, None, router, ErrorStream::new(), true);
+
+    // The error stream reads the queue next to the last branch.
+    let errors = Fork {
+        index: n,
+        shared: forks[0].shared.clone(),
+        queues: forks[0].queues.clone(),
+        poisoned: false,
+        budget: DEFAULT_BUDGET,
+    };
+
+    (forks, ForkErrors { fork: errors })
+}
+
+
+fn new<S, F, T, G>(
+    stream: S,
+    n: usize,
+    capacity: Option<usize>,
+    router: F,
+    errors: G,
+    error_queue: bool,
+) -> Vec<Fork<S, F, G>>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    assert!(n > 0, "fork needs at least one branch");
+
     let shared = Shared {
         router: router,
+        errors: errors,
         stream: stream,
+        routing: Routing::new(n, error_queue),
     };
 
     let shared = Arc::new(Mutex::new(shared));
 
-    let queues = Arc::new(Mutex::new(Queues::new()));
-
-    let left = Fork {
-        route: Side::Left,
-        shared: shared.clone(),
-        queues: queues.clone(),
-    };
+    let queues = Arc::new(Mutex::new(Queues::new(n, error_queue, capacity)));
 
-    let right = Fork {
-        route: Side::Right,
-        shared: shared,
-        queues: queues.clone(),
-    };
-
-    (left, right)
+    (0..n)
+        .map(|index| {
+            Fork {
+                index: index,
+                shared: shared.clone(),
+                queues: queues.clone(),
+                poisoned: false,
+                budget: DEFAULT_BUDGET,
+            }
+        })
+        .collect()
 }
 
 
@@ -54,47 +194,262 @@ impl From<bool> for Side {
 }
 
 
-#[derive(Debug, Clone)]
-struct Queues<T, E> {
-    left: VecDeque<Result<Option<T>, E>>,
-    right: VecDeque<Result<Option<T>, E>>,
+/// A decision of "router" of `fork` which can send an item to both branches or to neither.
+#[derive(Clone, Debug)]
+pub enum Multicast {
+    Left,
+    Right,
+    /// Both branches. The item is cloned.
+    Both,
+    /// No branch. The item is dropped.
+    Neither,
+}
+
+impl From<Side> for Multicast {
+    fn from(side: Side) -> Multicast {
+        match side {
+            Side::Left => Multicast::Left,
+            Side::Right => Multicast::Right,
+        }
+    }
+}
+
+
+/// A decision of "router" which tells the branches an item goes to.
+///
+/// This is implemented for `usize` (the index of branch), any type converting into `Side`
+/// (`Left` is 0 and `Right` is 1) such as `bool` (`true` is `Left`), `Multicast` and `Branches`.
+/// Only `Multicast` and `Branches` require `Clone` items because they can send an item to several
+/// branches. An item is cloned only when it goes to more than one branch.
+///
+/// You can implement it for your own type.
+///
+/// ```
+/// use ex_futures::stream::Route;
+///
+/// enum Level {
+///     Info,
+///     Warn,
+///     Error,
+/// }
+///
+/// impl<T> Route<T> for Level {
+///     fn route<P: FnMut(usize, T)>(self, item: T, mut push: P) {
+///         push(self as usize, item)
+///     }
+/// }
+/// ```
+pub trait Route<T> {
+    /// Passes `item` to `push` with the index of each branch it goes to.
+    fn route<P: FnMut(usize, T)>(self, item: T, push: P);
+}
+
+impl<T> Route<T> for usize {
+    fn route<P: FnMut(usize, T)>(self, item: T, mut push: P) {
+        push(self, item)
+    }
+}
+
+impl<T, R: Into<Side>> Route<T> for R {
+    fn route<P: FnMut(usize, T)>(self, item: T, mut push: P) {
+        match self.into() {
+            Side::Left => push(0, item),
+            Side::Right => push(1, item),
+        }
+    }
 }
 
+impl<T: Clone> Route<T> for Multicast {
+    fn route<P: FnMut(usize, T)>(self, item: T, mut push: P) {
+        match self {
+            Multicast::Left => push(0, item),
+            Multicast::Right => push(1, item),
+            Multicast::Both => {
+                push(0, item.clone());
+                push(1, item);
+            }
+            Multicast::Neither => (),
+        }
+    }
+}
 
-impl<T, E> Queues<T, E> {
-    fn new() -> Queues<T, E> {
-        Queues {
-            left: VecDeque::new(),
-            right: VecDeque::new(),
+impl<T: Clone> Route<T> for Branches {
+    fn route<P: FnMut(usize, T)>(self, item: T, mut push: P) {
+        let mut indexes = self.iter().peekable();
+        while let Some(index) = indexes.next() {
+            if indexes.peek().is_some() {
+                push(index, item.clone());
+            } else {
+                // The last branch takes the item itself.
+                push(index, item);
+                return;
+            }
         }
     }
+}
+
+
+/// A set of branches which is returned by "router" of `fork_n` to send an item to several
+/// branches.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Branches {
+    // Branches 0 to 63.
+    low: u64,
+    // Branches from 64. This does not allocate unless it is used.
+    high: Vec<u64>,
+}
+
+
+const WORD_BITS: usize = 64;
+
 
-    fn get_queue_mut(&mut self, route: Side) -> &mut VecDeque<Result<Option<T>, E>> {
-        match route {
-            Side::Left => &mut self.left,
-            Side::Right => &mut self.right,
+impl Branches {
+    /// Creates an empty set. An item is dropped if it goes to no branch.
+    pub fn new() -> Branches {
+        Branches::default()
+    }
+
+    /// Adds a branch.
+    pub fn insert(&mut self, index: usize) {
+        if index < WORD_BITS {
+            self.low |= 1 << index;
+        } else {
+            let word = index / WORD_BITS - 1;
+            if self.high.len() <= word {
+                self.high.resize(word + 1, 0);
+            }
+            self.high[word] |= 1 << (index % WORD_BITS);
         }
     }
 
-    fn push_none(&mut self) {
-        self.left.push_back(Ok(None));
-        self.right.push_back(Ok(None));
+    /// Adds a branch and returns itself.
+    pub fn with(mut self, index: usize) -> Branches {
+        self.insert(index);
+        self
     }
 
-    fn push_err(&mut self, err: E)
-    where
-        E: Clone,
-    {
-        self.left.push_back(Err(err.clone()));
-        self.right.push_back(Err(err));
+    pub fn contains(&self, index: usize) -> bool {
+        let word = if index < WORD_BITS {
+            self.low
+        } else {
+            self.high.get(index / WORD_BITS - 1).cloned().unwrap_or(0)
+        };
+        word & (1 << (index % WORD_BITS)) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.words().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words().all(|word| word == 0)
+    }
+
+    /// Returns indexes of branches in ascending order.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        self.words().enumerate().flat_map(|(i, word)| {
+            (0..WORD_BITS)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * WORD_BITS + bit)
+        })
+    }
+
+    fn words<'a>(&'a self) -> impl Iterator<Item = u64> + 'a {
+        Some(self.low).into_iter().chain(self.high.iter().cloned())
+    }
+}
+
+
+impl From<usize> for Branches {
+    fn from(index: usize) -> Branches {
+        Branches::new().with(index)
+    }
+}
+
+
+impl ::std::iter::FromIterator<usize> for Branches {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Branches {
+        let mut branches = Branches::new();
+        for index in iter {
+            branches.insert(index);
+        }
+        branches
+    }
+}
+
+
+/// A policy which tells the branches an error of original stream goes to.
+///
+/// This is implemented for `Broadcast` which sends a clone of the error to each branch, `usize`
+/// (the index of branch which receives every error), and closures which return `Route` like
+/// "router" does. Only `Broadcast` requires `Clone` errors.
+pub trait RouteError<E> {
+    /// Passes `err` to `push` with the index of each branch it goes to. `n` is the number of
+    /// branches.
+    fn route_error<P: FnMut(usize, E)>(&mut self, n: usize, err: E, push: P);
+}
+
+/// An error policy which sends a clone of each error to every branch. This is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Broadcast;
+
+/// An error policy which sends each error to the stream returned by `fork_n_with_error_stream`.
+#[derive(Clone, Copy, Debug)]
+pub struct ErrorStream(());
+
+impl ErrorStream {
+    pub(crate) fn new() -> ErrorStream {
+        ErrorStream(())
+    }
+}
+
+impl<E: Clone> RouteError<E> for Broadcast {
+    fn route_error<P: FnMut(usize, E)>(&mut self, n: usize, err: E, mut push: P) {
+        for index in 0..n - 1 {
+            push(index, err.clone());
+        }
+        push(n - 1, err);
+    }
+}
+
+impl<E> RouteError<E> for usize {
+    fn route_error<P: FnMut(usize, E)>(&mut self, _n: usize, err: E, mut push: P) {
+        push(*self, err)
+    }
+}
+
+impl<E, F, T> RouteError<E> for F
+where
+    F: FnMut(&E) -> T,
+    T: Route<E>,
+{
+    fn route_error<P: FnMut(usize, E)>(&mut self, _n: usize, err: E, push: P) {
+        self(&err).route(err, push)
     }
 }
 
+impl<E> RouteError<E> for ErrorStream {
+    fn route_error<P: FnMut(usize, E)>(&mut self, n: usize, err: E, mut push: P) {
+        // The error queue is next to the last branch.
+        push(n, err)
+    }
+}
 
-#[derive(Debug)]
-struct Shared<S: Stream, F> {
+
+struct Shared<S: Stream, F, G> {
     router: F,
+    errors: G,
     stream: S,
+    // Items and errors routed before `Queues` is locked.
+    routing: Routing<S::Item, S::Error>,
+}
+
+
+/// What original stream has produced in one poll.
+enum Polled {
+    Item,
+    Error,
+    End,
 }
 
 
@@ -103,7 +458,9 @@ struct Shared<S: Stream, F> {
 /// The closure being passed this function is called "router". Each item of original stream is
 /// passed to branch following to "router" decision.
 /// "Router" can return not only `Side` which is `Left` or `Right` but also
-/// `bool` (`true` is considered as `Left`).
+/// `bool` (`true` is considered as `Left`). To send an item to both branches or to neither,
+/// return `Multicast`. It requires `Clone` items because `Both` sends a clone of the item to
+/// each branch.
 ///
 /// # Examples
 ///
@@ -115,89 +472,294 @@ struct Shared<S: Stream, F> {
 /// # fn main() {
 /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
 ///
-/// let (even, odd) = rx.unsync_fork(|i| i % 2 == 0);
+/// let (even, odd) = rx.fork(|i| i % 2 == 0);
 /// # }
 /// ```
 ///
-/// # Notice
+/// If you need more than two branches, use `fork_n` function. Its "router" returns the index of
+/// branch, `Branches` or any type implementing `Route` trait.
+///
+/// Queues of branches are unbounded unless they are created by `fork_bounded` or
+/// `fork_n_bounded`. Then original stream is not polled while an item waits for the full
+/// queue of its branch.
+///
+/// If a branch is dropped, items routed to it are discarded unless another branch calls
+/// `set_fallback`. Original stream is dropped with the last branch.
+///
+/// An error of original stream is sent to every branch unless another error policy is given by
+/// `fork_with_errors`, `fork_n_with_errors` or `fork_n_with_error_stream`.
 ///
-/// The value being returned by this function is not `Sync`. We will provide `Sync` version later.
-pub struct Fork<S: Stream, F> {
-    route: Side,
+/// # Poisoning
+///
+/// If another thread panics while it polls original stream or routes an item, each branch returns
+/// `SharedError::Poisoned` after it reads the remaining items, and then finishes.
+/// The same happens if "router" of `fork_n` or the error policy returns an index out of range.
+pub struct Fork<S: Stream, F, G = Broadcast> {
+    index: usize,
     queues: Arc<Mutex<Queues<S::Item, S::Error>>>,
-    shared: Arc<Mutex<Shared<S, F>>>,
+    shared: Arc<Mutex<Shared<S, F, G>>>,
+    poisoned: bool,
+    // Maximum number of times one poll of this branch polls original stream.
+    budget: usize,
 }
 
 
-impl<S, F, T> Stream for Fork<S, F>
+impl<S, F, T, G> Stream for Fork<S, F, G>
 where
     S: Stream,
-    S::Error: Clone,
     F: FnMut(&S::Item) -> T,
-    T: Into<Side>,
+    T: Route<S::Item>,
+    G: RouteError<S::Error>,
 {
     type Item = S::Item;
-    type Error = S::Error;
-
-    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
-
-        // Check self queue
-        match self.queues
-            .lock()
-            .unwrap()
-            .get_queue_mut(self.route.clone())
-            .pop_front() {
-            Some(Ok(Some(msg))) => return Ok(Async::Ready(Some(msg))),
-            Some(Ok(None)) => return Ok(Async::Ready(None)),
-            Some(Err(e)) => return Err(e),
-            None => {
-                // Since queue is empty, we need to call poll at original future.
-            }
-        };
+    type Error = SharedError<S::Error>;
 
-        match self.shared.try_lock() {
-            Err(TryLockError::WouldBlock) => {
-                // Loop again
-            }
-            Err(TryLockError::Poisoned(_poisoned)) => {
-                // Currently we just panic thread if mutex get poisoned.
-                panic!("Other thread seemd to panic during processing cloned stream.");
+    fn poll(&mut self) -> Poll<Option<S::Item>, SharedError<S::Error>> {
+        if self.poisoned {
+            return Ok(Async::Ready(None));
+        }
+
+        let res = self.poll_shared();
+        if let Err(SharedError::Poisoned) = res {
+            // Finish this branch and let others know it.
+            self.poisoned = true;
+            lock_queues(&self.queues).notify_all();
+        }
+        res
+    }
+}
+
+
+
+impl<S, F, T, G> Fork<S, F, G>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+    G: RouteError<S::Error>,
+{
+    fn poll_shared(&mut self) -> Poll<Option<S::Item>, SharedError<S::Error>> {
+        let mut polled = 0;
+
+        loop {
+            // Check self queue
+            let msg = {
+                let mut queues = lock_queues(&self.queues);
+                let msg = queues.pop(self.index);
+                if msg.is_none() {
+                    // Register current task before trying to get `Shared`. So if other branch holds
+                    // `Shared` now, it will surely notify us after it pushes an item to our queue.
+                    queues.park(self.index);
+                }
+                msg
+            };
+
+            match msg {
+                Some(Ok(Some(msg))) => return Ok(Async::Ready(Some(msg))),
+                Some(Ok(None)) => return Ok(Async::Ready(None)),
+                Some(Err(e)) => return Err(SharedError::Inner(e)),
+                None => {
+                    // Since queue is empty, we need to call poll at original future.
+                }
+            };
+
+            if polled == self.budget {
+                // Original stream keeps producing items for other branches.
+                // Yield to other tasks, and poll it again soon.
+                task::current().notify();
+                return Ok(Async::NotReady);
             }
-            Ok(mut shared) => {
-                // Now we got shared value.
+            polled += 1;
+
+            {
+                let mut shared = match self.shared.try_lock() {
+                    Ok(shared) => shared,
+                    Err(TryLockError::WouldBlock) => {
+                        // The holder of `Shared` notifies us after it releases `Shared`. It may have
+                        // released `Shared` before we are blocked, so try again.
+                        lock_queues(&self.queues).block();
+                        match self.shared.try_lock() {
+                            Ok(shared) => shared,
+                            Err(TryLockError::WouldBlock) => return Ok(Async::NotReady),
+                            Err(TryLockError::Poisoned(_poisoned)) => {
+                                return Err(SharedError::Poisoned)
+                            }
+                        }
+                    }
+                    Err(TryLockError::Poisoned(_poisoned)) => {
+                        // Other thread panicked during polling original stream or routing an item.
+                        return Err(SharedError::Poisoned);
+                    }
+                };
 
-                match shared.stream.poll() {
-                    Err(e) => self.queues.lock().unwrap().push_err(e),
+                {
+                    let mut queues = lock_queues(&self.queues);
+                    if queues.is_paused() {
+                        // Some branch must read its full queue before original stream is polled.
+                        queues.block();
+                        return Ok(Async::NotReady);
+                    }
+                }
+
+                let shared = &mut *shared;
+                let result = match shared.stream.poll() {
+                    Err(e) => {
+                        shared.routing.route_error(&mut shared.errors, e);
+                        Polled::Error
+                    }
                     Ok(Async::Ready(Some(msg))) => {
-                        let route = (&mut shared.router)(&msg).into();
-                        self.queues.lock().unwrap().get_queue_mut(route).push_back(
-                            Ok(
-                                Some(msg),
-                            ),
-                        );
+                        let route = (&mut shared.router)(&msg);
+                        shared.routing.route_item(route, msg);
+                        Polled::Item
                     }
-                    Ok(Async::Ready(None)) => self.queues.lock().unwrap().push_none(),
+                    Ok(Async::Ready(None)) => Polled::End,
                     Ok(Async::NotReady) => {
+                        // Original stream notifies us, and we are parked for our queue too.
                         return Ok(Async::NotReady);
                     }
+                };
+
+                let mut queues = lock_queues(&self.queues);
+                queues.unpark(self.index); // We are going to read our queue right now.
+                match result {
+                    Polled::Error => queues.push_err(&mut shared.routing),
+                    Polled::Item => queues.push_item(&mut shared.routing),
+                    Polled::End => queues.push_none(),
                 }
             }
+
+            // Branches blocked while we held `Shared` can poll original stream now.
+            lock_queues(&self.queues).unblock();
         }
+    }
+}
+
+
+fn lock_queues<T, E>(queues: &Mutex<Queues<T, E>>) -> MutexGuard<'_, Queues<T, E>> {
+    match queues.lock() {
+        Ok(queues) => queues,
+        // `Queues` never panics while it is locked. Routing, which calls user code and checks
+        // indexes, is done before `Queues` is locked.
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+
+
+impl<S: Stream, F, G> Fork<S, F, G> {
+    /// Returns the index of this branch. `Left` is 0 and `Right` is 1.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the number of items which are routed to this branch but not read yet.
+    pub fn queue_len(&self) -> usize {
+        lock_queues(&self.queues).get_queue(self.index).len()
+    }
+
+    /// Returns `true` if original stream has finished.
+    pub fn is_terminated(&self) -> bool {
+        lock_queues(&self.queues).is_terminated()
+    }
+
+    /// Makes this branch receive items which are routed to dropped branches. Without a fallback
+    /// branch, those items are discarded.
+    pub fn set_fallback(&self) {
+        lock_queues(&self.queues).set_fallback(self.index);
+    }
 
-        // Loop if
-        // - queue is empty and could not get shared
-        // - queue is empty and get shared and new item is arrived
-        self.poll()
+    /// Returns the number of items which original stream has produced.
+    pub fn dispatched(&self) -> u64 {
+        lock_queues(&self.queues).dispatched()
+    }
+
+    /// Sets the maximum number of times one poll of this branch polls original stream without
+    /// getting an item. After that, the poll yields to other tasks by notifying current task and
+    /// returning `NotReady`. The default is 32.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `budget` is 0.
+    pub fn set_budget(&mut self, budget: usize) {
+        assert!(budget > 0, "budget must be positive");
+        self.budget = budget;
+    }
+}
+
+
+
+impl<S: Stream, F, G> Drop for Fork<S, F, G> {
+    fn drop(&mut self) {
+        lock_queues(&self.queues).close(self.index);
     }
 }
 
 
 
-impl<S: Stream, F> ::std::fmt::Debug for Fork<S, F> {
+impl<S: Stream, F, G> ::std::fmt::Debug for Fork<S, F, G> {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-        match self.route {
-            Side::Left => write!(f, "LeftRoute"),
-            Side::Right => write!(f, "RightRoute"),
+        let queues = lock_queues(&self.queues);
+        f.debug_struct("Fork")
+            .field("index", &self.index)
+            .field("queue_len", &queues.get_queue(self.index).len())
+            .field("terminated", &queues.is_terminated())
+            .field("dispatched", &queues.dispatched())
+            .finish()
+    }
+}
+
+
+
+/// A stream of errors of original stream being created by `fork_n_with_error_stream`.
+///
+/// Each error of original stream comes here as an item instead of going to branches. This
+/// finishes when original stream finishes. Like branches, this returns `SharedError::Poisoned` if
+/// another thread panics while it polls original stream.
+pub struct ForkErrors<S: Stream, F> {
+    fork: Fork<S, F, ErrorStream>,
+}
+
+
+impl<S, F, T> Stream for ForkErrors<S, F>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    type Item = S::Error;
+    type Error = SharedError<()>;
+
+    fn poll(&mut self) -> Poll<Option<S::Error>, SharedError<()>> {
+        match self.fork.poll() {
+            Ok(Async::Ready(Some(_))) => unreachable!("an item is routed to the error stream"),
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(SharedError::Inner(e)) => Ok(Async::Ready(Some(e))),
+            // Forks never evict branches, so this is `SharedError::Poisoned`.
+            Err(_) => Err(SharedError::Poisoned),
         }
     }
 }
+
+
+impl<S: Stream, F> ForkErrors<S, F> {
+    /// Sets the maximum number of times one poll of this stream polls original stream without
+    /// getting an error. Please have a look at `set_budget` of branches.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `budget` is 0.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.fork.set_budget(budget);
+    }
+}
+
+
+impl<S: Stream, F> ::std::fmt::Debug for ForkErrors<S, F> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        f.debug_struct("ForkErrors")
+            .field("queue_len", &self.fork.queue_len())
+            .field("terminated", &self.fork.is_terminated())
+            .finish()
+    }
+}
@@ -0,0 +1,246 @@
+use futures::task::{self, Task};
+
+use super::fork::{Route, RouteError};
+
+use std::collections::VecDeque;
+
+
+/// Items and errors which are routed but not queued yet.
+///
+/// Routing calls user code and checks the indexes it returns, so it is done before `Queues` is
+/// touched. Then a router choosing a wrong branch panics without breaking `Queues`.
+#[derive(Debug)]
+pub(crate) struct Routing<T, E> {
+    // Number of branches, and of queues including the error queue of `ErrorStream`.
+    branches: usize,
+    queue_count: usize,
+    items: Vec<(usize, T)>,
+    errors: Vec<(usize, E)>,
+}
+
+
+impl<T, E> Routing<T, E> {
+    pub(crate) fn new(n: usize, error_queue: bool) -> Routing<T, E> {
+        Routing {
+            branches: n,
+            queue_count: if error_queue { n + 1 } else { n },
+            items: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub(crate) fn route_item<R: Route<T>>(&mut self, route: R, item: T) {
+        // A router which panicked may have left a part of its item.
+        self.items.clear();
+        let branches = self.branches;
+        let routed = &mut self.items;
+        route.route(item, |index, item| {
+            assert!(
+                index < branches,
+                "router chose branch {} but there are only {} branches",
+                index,
+                branches
+            );
+            routed.push((index, item));
+        });
+    }
+
+    pub(crate) fn route_error<G: RouteError<E>>(&mut self, errors: &mut G, err: E) {
+        self.errors.clear();
+        let branches = self.branches;
+        let queue_count = self.queue_count;
+        let routed = &mut self.errors;
+        errors.route_error(branches, err, |index, err| {
+            assert!(
+                index < queue_count,
+                "error router chose branch {} but there are only {} branches",
+                index,
+                branches
+            );
+            routed.push((index, err));
+        });
+    }
+}
+
+
+
+/// Queues of branches shared by `Fork` and `UnsyncFork`.
+#[derive(Debug)]
+pub(crate) struct Queues<T, E> {
+    queues: Vec<VecDeque<Result<Option<T>, E>>>,
+    // Maximum length of each queue. `None` means queues are unbounded.
+    capacity: Option<usize>,
+    // Items routed to full queues. Original stream is not polled until all of them are queued.
+    pending: VecDeque<(usize, T)>,
+    // Task of each branch waiting for a new item in its queue.
+    tasks: Vec<Option<Task>>,
+    // Tasks of branches waiting to poll original stream.
+    blocked: Vec<Task>,
+    // `false` if the branch has been dropped.
+    alive: Vec<bool>,
+    // Branch receiving items which are routed to dropped branches.
+    fallback: Option<usize>,
+    // Number of items which original stream has produced.
+    dispatched: u64,
+    terminated: bool,
+}
+
+
+impl<T, E> Queues<T, E> {
+    pub(crate) fn new(n: usize, error_queue: bool, capacity: Option<usize>) -> Queues<T, E> {
+        let len = if error_queue { n + 1 } else { n };
+        Queues {
+            queues: (0..len).map(|_| VecDeque::new()).collect(),
+            capacity: capacity,
+            pending: VecDeque::new(),
+            tasks: (0..len).map(|_| None).collect(),
+            blocked: Vec::new(),
+            alive: vec![true; len],
+            fallback: None,
+            dispatched: 0,
+            terminated: false,
+        }
+    }
+
+    pub(crate) fn get_queue(&self, index: usize) -> &VecDeque<Result<Option<T>, E>> {
+        &self.queues[index]
+    }
+
+    pub(crate) fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    pub(crate) fn dispatched(&self) -> u64 {
+        self.dispatched
+    }
+
+    pub(crate) fn set_fallback(&mut self, index: usize) {
+        self.fallback = Some(index);
+    }
+
+    /// Pops an item of given branch and moves its pending items into the freed space.
+    pub(crate) fn pop(&mut self, index: usize) -> Option<Result<Option<T>, E>> {
+        let msg = self.queues[index].pop_front();
+        if msg.is_some() && !self.pending.is_empty() {
+            self.flush(index);
+        }
+        msg
+    }
+
+    fn flush(&mut self, index: usize) {
+        while !is_full(&self.queues[index], self.capacity) {
+            match self.pending.iter().position(|&(i, _)| i == index) {
+                Some(pos) => {
+                    let (_, item) = self.pending.remove(pos).unwrap();
+                    self.queues[index].push_back(Ok(Some(item)));
+                }
+                None => break,
+            }
+        }
+
+        self.unblock();
+    }
+
+    /// Notifies branches waiting to poll original stream unless it is paused.
+    pub(crate) fn unblock(&mut self) {
+        if self.pending.is_empty() {
+            for task in self.blocked.drain(..) {
+                task.notify();
+            }
+        }
+    }
+
+    /// Notifies every waiting branch. One of them will poll original stream instead of the branch
+    /// which may be the only one original stream notifies.
+    pub(crate) fn notify_all(&mut self) {
+        for task in self.tasks.iter_mut().filter_map(Option::take) {
+            task.notify();
+        }
+        for task in self.blocked.drain(..) {
+            task.notify();
+        }
+    }
+
+    /// Discards items of dropped branch. Items routed to it later are discarded too unless there
+    /// is a fallback branch.
+    pub(crate) fn close(&mut self, index: usize) {
+        self.alive[index] = false;
+        self.queues[index].clear();
+        self.tasks[index] = None;
+        if self.fallback == Some(index) {
+            self.fallback = None;
+        }
+
+        self.pending.retain(|&(i, _)| i != index);
+        self.notify_all();
+    }
+
+    /// Returns `true` if original stream must not be polled until some branch reads its queue.
+    pub(crate) fn is_paused(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub(crate) fn park(&mut self, index: usize) {
+        self.tasks[index] = Some(task::current());
+    }
+
+    pub(crate) fn unpark(&mut self, index: usize) {
+        self.tasks[index] = None;
+    }
+
+    pub(crate) fn block(&mut self) {
+        self.blocked.push(task::current());
+    }
+
+    /// Queues an item routed by `Routing::route_item`.
+    pub(crate) fn push_item(&mut self, routing: &mut Routing<T, E>) {
+        self.dispatched += 1;
+
+        for (index, item) in routing.items.drain(..) {
+            let index = match self.fallback {
+                _ if self.alive[index] => index,
+                Some(fallback) => fallback,
+                None => continue,
+            };
+            if is_full(&self.queues[index], self.capacity) {
+                self.pending.push_back((index, item));
+            } else {
+                self.queues[index].push_back(Ok(Some(item)));
+                notify(&mut self.tasks[index]);
+            }
+        }
+    }
+
+    pub(crate) fn push_none(&mut self) {
+        self.terminated = true;
+        for index in 0..self.queues.len() {
+            if self.alive[index] {
+                self.queues[index].push_back(Ok(None));
+                notify(&mut self.tasks[index]);
+            }
+        }
+    }
+
+    /// Queues an error routed by `Routing::route_error`.
+    pub(crate) fn push_err(&mut self, routing: &mut Routing<T, E>) {
+        for (index, err) in routing.errors.drain(..) {
+            // Errors are not redirected to the fallback branch.
+            if self.alive[index] {
+                self.queues[index].push_back(Err(err));
+                notify(&mut self.tasks[index]);
+            }
+        }
+    }
+}
+
+
+fn is_full<T>(queue: &VecDeque<T>, capacity: Option<usize>) -> bool {
+    capacity.map(|capacity| queue.len() >= capacity).unwrap_or(false)
+}
+
+
+fn notify(task: &mut Option<Task>) {
+    if let Some(task) = task.take() {
+        task.notify();
+    }
+}
@@ -0,0 +1,414 @@
+use futures::{Stream, Poll, Async};
+use futures::task::{self, Task};
+
+use util::SharedCell;
+use super::DEFAULT_BUDGET;
+
+use std::rc::Rc;
+use std::collections::{VecDeque, HashMap};
+use std::hash::Hash;
+
+
+/// Split given stream into groups. Please have a look at document of `StreamExt` trait.
+pub fn group_by<S, F, K>(stream: S, key_fn: F) -> GroupBy<S, F, K>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> K,
+    K: Eq + Hash + Clone,
+{
+    let shared = Shared {
+        stream: stream,
+        key_fn: key_fn,
+        groups: HashMap::new(),
+        emitted: VecDeque::new(),
+        task: None,
+        has_outer: true,
+        terminated: false,
+    };
+
+    GroupBy { shared: Rc::new(SharedCell::new(shared)) }
+}
+
+
+struct Queue<T, E> {
+    items: VecDeque<Result<Option<T>, E>>,
+    // Task of the group waiting for a new item.
+    task: Option<Task>,
+}
+
+
+impl<T, E> Queue<T, E> {
+    fn new() -> Queue<T, E> {
+        Queue {
+            items: VecDeque::new(),
+            task: None,
+        }
+    }
+
+    fn push(&mut self, msg: Result<Option<T>, E>) {
+        self.items.push_back(msg);
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+    }
+}
+
+
+/// A handle which is dropped while `Shared` is borrowed.
+enum Handle<K> {
+    GroupBy,
+    Group(K),
+}
+
+
+struct Shared<S: Stream, F, K> {
+    stream: S,
+    key_fn: F,
+    // Queues of groups. A group is removed when it is dropped.
+    groups: HashMap<K, Queue<S::Item, S::Error>>,
+    // Keys of new groups, errors and the end for `GroupBy`.
+    emitted: VecDeque<Result<Option<K>, S::Error>>,
+    // Task of `GroupBy` waiting for a new group.
+    task: Option<Task>,
+    has_outer: bool,
+    terminated: bool,
+}
+
+
+impl<S, F, K> Shared<S, F, K>
+where
+    S: Stream,
+    S::Error: Clone,
+    F: FnMut(&S::Item) -> K,
+    K: Eq + Hash + Clone,
+{
+    /// Polls original stream once and passes the result to each queue.
+    fn poll_stream(&mut self, cell: &SharedCell<Self, Handle<K>>) -> Async<()> {
+        let msg = match self.stream.poll() {
+            Ok(Async::NotReady) => return Async::NotReady,
+            Ok(Async::Ready(Some(msg))) => msg,
+            Ok(Async::Ready(None)) => {
+                self.terminated = true;
+                for queue in self.groups.values_mut() {
+                    queue.push(Ok(None));
+                }
+                self.emit(Ok(None));
+                return Async::Ready(());
+            }
+            Err(e) => {
+                for queue in self.groups.values_mut() {
+                    queue.push(Err(e.clone()));
+                }
+                self.emit(Err(e));
+                return Async::Ready(());
+            }
+        };
+
+        let key = (self.key_fn)(&msg);
+
+        // Original stream or `key_fn` may drop groups. A new group must be created for their keys.
+        self.remove_dropped(cell);
+
+        if let Some(queue) = self.groups.get_mut(&key) {
+            queue.push(Ok(Some(msg)));
+            return Async::Ready(());
+        }
+
+        // Nobody receives a new group after `GroupBy` is dropped.
+        if self.has_outer {
+            let mut queue = Queue::new();
+            queue.push(Ok(Some(msg)));
+            self.groups.insert(key.clone(), queue);
+            self.emit(Ok(Some(key)));
+        }
+
+        Async::Ready(())
+    }
+
+    fn emit(&mut self, msg: Result<Option<K>, S::Error>) {
+        if !self.has_outer {
+            return;
+        }
+        self.emitted.push_back(msg);
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+    }
+}
+
+
+impl<S, F, K> Shared<S, F, K>
+where
+    S: Stream,
+    K: Eq + Hash,
+{
+    fn remove(&mut self, handle: Handle<K>) {
+        match handle {
+            Handle::GroupBy => {
+                self.has_outer = false;
+                self.task = None;
+
+                // Nobody receives groups which are not emitted yet.
+                let emitted = ::std::mem::take(&mut self.emitted);
+                for msg in emitted {
+                    if let Ok(Some(key)) = msg {
+                        self.groups.remove(&key);
+                    }
+                }
+            }
+            Handle::Group(key) => {
+                self.groups.remove(&key);
+            }
+        }
+
+        // Original stream may notify only the dropped handle which polled it last.
+        self.notify_all();
+    }
+
+    /// Notifies every waiting handle. One of them will poll original stream instead of the
+    /// dropped handle.
+    fn notify_all(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+        for task in self.groups.values_mut().filter_map(|queue| queue.task.take()) {
+            task.notify();
+        }
+    }
+
+    /// Removes handles which are dropped while `Shared` is borrowed.
+    fn remove_dropped(&mut self, cell: &SharedCell<Self, Handle<K>>) {
+        loop {
+            let dropped = cell.take_dropped();
+            if dropped.is_empty() {
+                return;
+            }
+
+            for handle in dropped {
+                self.remove(handle);
+            }
+        }
+    }
+}
+
+
+
+/// A stream of groups being created by `group_by` function.
+///
+/// Each item is a pair of a key and a `Group` which is a stream of items having that key.
+/// A new group is emitted when a new key appears, and every group finishes when original stream
+/// finishes. An error of original stream is passed to every group and to this stream.
+///
+/// If a group is dropped, its items are discarded, and a new group is emitted when its key
+/// appears again.
+///
+/// # Notice
+///
+/// The value being returned by this function is not `Sync`.
+pub struct GroupBy<S, F, K>
+where
+    S: Stream,
+    K: Eq + Hash,
+{
+    shared: Rc<SharedCell<Shared<S, F, K>, Handle<K>>>,
+}
+
+
+
+impl<S, F, K> Stream for GroupBy<S, F, K>
+where
+    S: Stream,
+    S::Error: Clone,
+    F: FnMut(&S::Item) -> K,
+    K: Eq + Hash + Clone,
+{
+    type Item = (K, Group<S, F, K>);
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<(K, Group<S, F, K>)>, S::Error> {
+        let mut shared = self.shared.borrow_mut(); // Never panics because this is unsync.
+        let mut polled = 0;
+
+        loop {
+            shared.remove_dropped(&self.shared);
+
+            match shared.emitted.pop_front() {
+                Some(Ok(Some(key))) => {
+                    let group = Group {
+                        key: Some(key.clone()),
+                        shared: self.shared.clone(),
+                    };
+                    return Ok(Async::Ready(Some((key, group))));
+                }
+                Some(Ok(None)) => return Ok(Async::Ready(None)),
+                Some(Err(e)) => return Err(e),
+                None => (),
+            }
+
+            if shared.terminated {
+                return Ok(Async::Ready(None));
+            }
+
+            if polled == DEFAULT_BUDGET {
+                // Original stream keeps producing items for other groups.
+                // Yield to other tasks, and poll it again soon.
+                task::current().notify();
+                return Ok(Async::NotReady);
+            }
+            polled += 1;
+
+            if let Async::NotReady = shared.poll_stream(&self.shared) {
+                shared.task = Some(task::current());
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
+
+
+impl<S, F, K> Drop for GroupBy<S, F, K>
+where
+    S: Stream,
+    K: Eq + Hash,
+{
+    fn drop(&mut self) {
+        // Original stream may drop this while it is polled. Then the poller removes this.
+        if let Some((mut shared, handle)) = self.shared.borrow_or_defer(Handle::GroupBy) {
+            shared.remove(handle);
+            // Removed groups may hold other handles.
+            shared.remove_dropped(&self.shared);
+        }
+    }
+}
+
+
+
+impl<S, F, K> ::std::fmt::Debug for GroupBy<S, F, K>
+where
+    S: Stream,
+    K: Eq + Hash,
+{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        let shared = self.shared.borrow();
+        f.debug_struct("GroupBy")
+            .field("group_count", &shared.groups.len())
+            .field("terminated", &shared.terminated)
+            .finish()
+    }
+}
+
+
+
+/// A stream of items having the same key. This is emitted by `GroupBy` stream.
+pub struct Group<S, F, K>
+where
+    S: Stream,
+    K: Eq + Hash,
+{
+    // `None` only while this is dropped.
+    key: Option<K>,
+    shared: Rc<SharedCell<Shared<S, F, K>, Handle<K>>>,
+}
+
+
+
+impl<S, F, K> Group<S, F, K>
+where
+    S: Stream,
+    K: Eq + Hash,
+{
+    /// Returns the key of this group.
+    pub fn key(&self) -> &K {
+        self.key.as_ref().unwrap()
+    }
+}
+
+
+
+impl<S, F, K> Stream for Group<S, F, K>
+where
+    S: Stream,
+    S::Error: Clone,
+    F: FnMut(&S::Item) -> K,
+    K: Eq + Hash + Clone,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        let mut shared = self.shared.borrow_mut(); // Never panics because this is unsync.
+        let mut polled = 0;
+
+        loop {
+            shared.remove_dropped(&self.shared);
+
+            let msg = shared.groups.get_mut(self.key()).and_then(
+                |queue| queue.items.pop_front(),
+            );
+
+            match msg {
+                Some(Ok(Some(msg))) => return Ok(Async::Ready(Some(msg))),
+                Some(Ok(None)) => return Ok(Async::Ready(None)),
+                Some(Err(e)) => return Err(e),
+                None => (),
+            }
+
+            if shared.terminated {
+                return Ok(Async::Ready(None));
+            }
+
+            if polled == DEFAULT_BUDGET {
+                // Original stream keeps producing items for other groups.
+                // Yield to other tasks, and poll it again soon.
+                task::current().notify();
+                return Ok(Async::NotReady);
+            }
+            polled += 1;
+
+            if let Async::NotReady = shared.poll_stream(&self.shared) {
+                if let Some(queue) = shared.groups.get_mut(self.key()) {
+                    queue.task = Some(task::current());
+                }
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
+
+
+impl<S, F, K> Drop for Group<S, F, K>
+where
+    S: Stream,
+    K: Eq + Hash,
+{
+    fn drop(&mut self) {
+        let key = self.key.take().unwrap();
+        // Original stream may drop a group while it is polled. Then the poller removes it.
+        if let Some((mut shared, handle)) = self.shared.borrow_or_defer(Handle::Group(key)) {
+            shared.remove(handle);
+            // Items of this group may hold other handles.
+            shared.remove_dropped(&self.shared);
+        }
+    }
+}
+
+
+
+impl<S, F, K> ::std::fmt::Debug for Group<S, F, K>
+where
+    S: Stream,
+    K: Eq + Hash + ::std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        let shared = self.shared.borrow();
+        f.debug_struct("Group")
+            .field("key", self.key())
+            .field(
+                "queue_len",
+                &shared.groups.get(self.key()).map(|q| q.items.len()).unwrap_or(0),
+            )
+            .finish()
+    }
+}
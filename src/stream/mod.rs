@@ -1,19 +1,39 @@
 mod unsync_cloneable;
 mod fork;
+mod fork_queues;
 mod unsync_fork;
 mod cloneable;
 mod find_first_map;
 mod find_first;
+mod group_by;
+mod split;
+mod share_auto;
+mod unsync_share_auto;
 
-pub use self::cloneable::Cloneable;
-pub use self::unsync_cloneable::UnsyncCloneable;
+pub use self::cloneable::{Cloneable, WeakCloneable, Eviction, Connectable, Connection};
+pub use self::unsync_cloneable::{UnsyncCloneable, WeakUnsyncCloneable, UnsyncConnectable,
+                                  UnsyncConnection};
 pub use self::find_first_map::FindFirstMap;
 pub use self::find_first::FindFirst;
-pub use self::fork::{LeftFork, RightFork, Fork, Side};
-pub use self::unsync_fork::{LeftUnsyncFork, RightUnsyncFork, UnsyncFork};
+pub use self::group_by::{GroupBy, Group};
+pub use self::share_auto::{share_auto, ShareAuto};
+pub use self::unsync_share_auto::{unsync_share_auto, UnsyncShareAuto};
+pub use self::fork::{LeftFork, RightFork, ForkWithErrorStream, Fork, ForkErrors, Side, Multicast,
+                     Route, Branches, RouteError, Broadcast, ErrorStream};
+pub use self::unsync_fork::{LeftUnsyncFork, RightUnsyncFork, UnsyncForkWithErrorStream, UnsyncFork,
+                            UnsyncForkErrors};
+pub use self::split::{Either, Split, SplitLeft, SplitRight};
 
 use futures::Stream;
-use futures::stream::Then;
+use futures::stream::{Then, Map, MapErr};
+
+use std::sync::Arc;
+use std::rc::Rc;
+use std::hash::Hash;
+
+
+// Maximum number of times one poll of a shared stream polls original stream by default.
+const DEFAULT_BUDGET: usize = 32;
 
 
 pub type AsErr<S: Stream, E> = Then<
@@ -22,6 +42,25 @@ pub type AsErr<S: Stream, E> = Then<
     Result<S::Item, E>,
 >;
 
+/// A stream whose items and errors are wrapped by `Arc`. Please have a look at
+/// `StreamExt::shared_items`.
+pub type ArcItems<S> = MapErr<
+    Map<S, fn(<S as Stream>::Item) -> Arc<<S as Stream>::Item>>,
+    fn(<S as Stream>::Error) -> Arc<<S as Stream>::Error>,
+>;
+
+
+/// A stream whose items and errors are wrapped by `Rc`. Please have a look at
+/// `StreamExt::unsync_shared_items`.
+pub type RcItems<S> = MapErr<
+    Map<S, fn(<S as Stream>::Item) -> Rc<<S as Stream>::Item>>,
+    fn(<S as Stream>::Error) -> Rc<<S as Stream>::Error>,
+>;
+
+
+/// Streams returned by `StreamExt::split_either`.
+pub type SplitEither<S, F> = (SplitLeft<Map<S, F>>, SplitRight<Map<S, F>>);
+
 /// An extention of `Stream` provided by `futures` crate.
 /// Any `Stream` implements `StreamExt` automatically.
 /// All you is to import `StreamExt`.
@@ -33,10 +72,17 @@ pub trait StreamExt: Stream {
     /// Convert any kind of stream into "cloneable" stream.
     /// The `Item` and `Error` need to implement `Clone`. If not, consider wrap it by `Arc`.
     ///
+    /// Each item of original stream is stored only once in a buffer shared by all clones.
+    /// Each clone has its own cursor on the buffer and clones the item when it reads it.
+    /// So polling does not get slow even if there are many clones, unless the stream is created
+    /// by `cloneable_bounded` or `cloneable_evicting` function.
+    ///
+    /// An error of original stream is wrapped by `SharedError::Inner`. If another thread panics
+    /// while it polls original stream, clones return `SharedError::Poisoned` and then finish.
+    ///
     /// # Notice
     ///
-    /// This feature is work. But does not have high performance.
-    /// If you need not to use `Sync`, please use `unsync_cloneable` function. That is fast enough.
+    /// If you need not to use `Sync`, please use `unsync_cloneable` function. That is faster.
     ///
     /// # Examples
     ///
@@ -62,6 +108,209 @@ pub trait StreamExt: Stream {
     }
 
 
+    /// Convert any kind of stream into "cloneable" stream whose queues have limited capacity.
+    /// While any clone has `capacity` items in its queue, original stream is not polled.
+    /// The stream resumes when that clone consumes its queue.
+    ///
+    /// Every queue is checked before original stream is polled, so polling takes time
+    /// proportional to the number of clones.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `capacity` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    ///
+    /// # fn main() {
+    /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
+    ///
+    /// let cloneable_rx = rx.cloneable_bounded(16); // Each queue holds at most 16 items.
+    /// let cloneable_rx2 = cloneable_rx.clone();
+    /// # }
+    /// ```
+    fn cloneable_bounded(self, capacity: usize) -> Cloneable<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        Self::Error: Clone,
+    {
+        self::cloneable::cloneable_bounded(self, capacity)
+    }
+
+
+    /// Convert any kind of stream into "cloneable" stream which keeps every item.
+    /// A clone created later reads every item from the beginning of original stream.
+    ///
+    /// # Notice
+    ///
+    /// Kept items are never freed until every clone is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Stream, Future};
+    /// use futures::stream::iter_ok;
+    ///
+    /// # fn main() {
+    /// let cached = iter_ok::<_, ()>(vec![0, 1, 2]).cached();
+    /// let cached2 = cached.clone();
+    ///
+    /// assert_eq!(cached.collect().wait().unwrap(), [0, 1, 2]);
+    ///
+    /// // A clone created after original stream is consumed.
+    /// assert_eq!(cached2.clone().collect().wait().unwrap(), [0, 1, 2]);
+    /// # }
+    /// ```
+    fn cached(self) -> Cloneable<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        Self::Error: Clone,
+    {
+        self::cloneable::cached(self)
+    }
+
+
+    /// Convert any kind of stream into "cloneable" stream which keeps last `n` items.
+    /// A clone created later reads those items at first.
+    /// Errors and the end of the stream following those items are
+    /// kept too, but they do not count toward `n`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    ///
+    /// # fn main() {
+    /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
+    ///
+    /// let replayable_rx = rx.replayable(8); // A new clone reads last 8 items at first.
+    /// let replayable_rx2 = replayable_rx.clone();
+    /// # }
+    /// ```
+    fn replayable(self, n: usize) -> Cloneable<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        Self::Error: Clone,
+    {
+        self::cloneable::replayable(self, n)
+    }
+
+
+    /// Convert any kind of stream into "cloneable" stream which evicts clones falling behind.
+    /// An evicted clone returns `SharedError::Evicted` and then finishes, while other clones
+    /// continue.
+    ///
+    /// Every clone is checked after original stream produces an item, so polling takes time
+    /// proportional to the number of clones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use ex_futures::stream::Eviction;
+    /// use std::time::Duration;
+    ///
+    /// # fn main() {
+    /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
+    ///
+    /// // Evicts a clone when it has more than 64 unread items.
+    /// let evicting_rx = rx.cloneable_evicting(Eviction::Backlog(64));
+    /// let evicting_rx2 = evicting_rx.clone();
+    ///
+    /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
+    ///
+    /// // Evicts a clone when its oldest unread item has been waiting more than a second.
+    /// let evicting_rx = rx.cloneable_evicting(Eviction::Age(Duration::from_secs(1)));
+    /// # }
+    /// ```
+    fn cloneable_evicting(self, eviction: Eviction) -> Cloneable<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        Self::Error: Clone,
+    {
+        self::cloneable::cloneable_evicting(self, eviction)
+    }
+
+
+    /// Convert any kind of stream into `Connectable`, a source of "cloneable" streams.
+    /// Original stream is not polled until `Connectable::connect` is called. So clones created
+    /// before that never miss the first items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// let published = ::futures::stream::iter_ok::<_, ()>(0..3).publish();
+    ///
+    /// let rx1 = published.subscribe();
+    /// let rx2 = published.subscribe();
+    ///
+    /// let connection = published.connect(); // Original stream is polled from now on.
+    ///
+    /// assert_eq!(rx1.collect().wait(), Ok(vec![0, 1, 2]));
+    /// assert_eq!(rx2.collect().wait(), Ok(vec![0, 1, 2]));
+    /// # }
+    /// ```
+    fn publish(self) -> Connectable<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        Self::Error: Clone,
+    {
+        self::cloneable::publish(self)
+    }
+
+
+    /// Convert any kind of stream into "cloneable" stream whose items and errors are wrapped by
+    /// `Arc`. Each item and error is wrapped only once and is shared by all clones. So neither
+    /// `Item` nor `Error` needs to implement `Clone`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    ///
+    /// # fn main() {
+    /// struct LargeBuffer(Vec<u8>); // Not `Clone`.
+    ///
+    /// let (tx, rx) = ::futures::sync::mpsc::channel::<LargeBuffer>(42);
+    ///
+    /// let shared_rx = rx.shared_items(); // Stream of `Arc<LargeBuffer>`.
+    /// let shared_rx2 = shared_rx.clone();
+    /// # }
+    /// ```
+    fn shared_items(self) -> Cloneable<ArcItems<Self>>
+    where
+        Self: Sized,
+    {
+        self.map(Arc::new as fn(Self::Item) -> Arc<Self::Item>)
+            .map_err(Arc::new as fn(Self::Error) -> Arc<Self::Error>)
+            .cloneable()
+    }
+
+
     /// Convert any kind of stream into "cloneable" stream but unsync.
     /// If your stream emits non `Clone` item or error, consider wrap it by `Rc`.
     ///
@@ -95,11 +344,14 @@ pub trait StreamExt: Stream {
         self::unsync_cloneable::unsync_cloneable(self)
     }
 
-    /// Fork any kind of stream into two stream like that the river branches.
-    /// The closure being passed this function is called "router". Each item of original stream is
-    /// passed to branch following to "router" decision.
-    /// "Router" can return not only `Side` which is `Left` or `Right` but also
-    /// `bool` (`true` is considered as `Left`).
+
+    /// Convert any kind of stream into "cloneable" stream but unsync. Each queue has limited
+    /// capacity. While any clone has `capacity` items in its queue, original stream is not
+    /// polled. The stream resumes when that clone consumes its queue.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `capacity` is 0.
     ///
     /// # Examples
     ///
@@ -111,25 +363,157 @@ pub trait StreamExt: Stream {
     /// # fn main() {
     /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
     ///
-    /// let (even, odd) = rx.fork(|i| i % 2 == 0);
+    /// let cloneable_rx = rx.unsync_cloneable_bounded(16); // Each queue holds at most 16 items.
+    /// let cloneable_rx2 = cloneable_rx.clone();
     /// # }
     /// ```
-    fn fork<F, T>(self, router: F) -> (LeftFork<Self, F>, RightFork<Self, F>)
+    fn unsync_cloneable_bounded(self, capacity: usize) -> UnsyncCloneable<Self>
     where
         Self: Sized,
+        Self::Item: Clone,
         Self::Error: Clone,
-        F: FnMut(&Self::Item) -> T,
-        Side: From<T>,
     {
-        self::fork::fork(self, router)
+        self::unsync_cloneable::unsync_cloneable_bounded(self, capacity)
     }
 
 
-    /// Fork any kind of stream into two "unsync" stream.
+    /// Convert any kind of stream into "cloneable" stream which keeps every item, but unsync.
+    /// A clone created later reads every item from the beginning of original stream.
+    ///
+    /// # Notice
+    ///
+    /// Kept items are never freed until every clone is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Stream, Future};
+    /// use futures::stream::iter_ok;
+    ///
+    /// # fn main() {
+    /// let cached = iter_ok::<_, ()>(vec![0, 1, 2]).unsync_cached();
+    /// let cached2 = cached.clone();
+    ///
+    /// assert_eq!(cached.collect().wait().unwrap(), [0, 1, 2]);
+    ///
+    /// // A clone created after original stream is consumed.
+    /// assert_eq!(cached2.clone().collect().wait().unwrap(), [0, 1, 2]);
+    /// # }
+    /// ```
+    fn unsync_cached(self) -> UnsyncCloneable<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        Self::Error: Clone,
+    {
+        self::unsync_cloneable::unsync_cached(self)
+    }
+
+
+    /// Convert any kind of stream into "cloneable" stream which keeps last `n` items, but unsync.
+    /// A clone created later reads those items at first.
+    /// Errors and the end of the stream following those items are
+    /// kept too, but they do not count toward `n`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    ///
+    /// # fn main() {
+    /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
+    ///
+    /// let replayable_rx = rx.unsync_replayable(8); // A new clone reads last 8 items at first.
+    /// let replayable_rx2 = replayable_rx.clone();
+    /// # }
+    /// ```
+    fn unsync_replayable(self, n: usize) -> UnsyncCloneable<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        Self::Error: Clone,
+    {
+        self::unsync_cloneable::unsync_replayable(self, n)
+    }
+
+
+    /// Convert any kind of stream into `UnsyncConnectable`, a source of "cloneable" streams but
+    /// unsync. Original stream is not polled until `UnsyncConnectable::connect` is called.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// let published = ::futures::stream::iter_ok::<_, ()>(0..3).unsync_publish();
+    ///
+    /// let rx1 = published.subscribe();
+    /// let rx2 = published.subscribe();
+    ///
+    /// let connection = published.connect(); // Original stream is polled from now on.
+    ///
+    /// assert_eq!(rx1.collect().wait(), Ok(vec![0, 1, 2]));
+    /// assert_eq!(rx2.collect().wait(), Ok(vec![0, 1, 2]));
+    /// # }
+    /// ```
+    fn unsync_publish(self) -> UnsyncConnectable<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        Self::Error: Clone,
+    {
+        self::unsync_cloneable::unsync_publish(self)
+    }
+
+
+    /// Convert any kind of stream into "cloneable" stream whose items and errors are wrapped by
+    /// `Rc`, but unsync. Each item and error is wrapped only once and is shared by all clones.
+    /// So neither `Item` nor `Error` needs to implement `Clone`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    ///
+    /// # fn main() {
+    /// struct LargeBuffer(Vec<u8>); // Not `Clone`.
+    ///
+    /// let (tx, rx) = ::futures::sync::mpsc::channel::<LargeBuffer>(42);
+    ///
+    /// let shared_rx = rx.unsync_shared_items(); // Stream of `Rc<LargeBuffer>`.
+    /// let shared_rx2 = shared_rx.clone();
+    /// # }
+    /// ```
+    fn unsync_shared_items(self) -> UnsyncCloneable<RcItems<Self>>
+    where
+        Self: Sized,
+    {
+        self.map(Rc::new as fn(Self::Item) -> Rc<Self::Item>)
+            .map_err(Rc::new as fn(Self::Error) -> Rc<Self::Error>)
+            .unsync_cloneable()
+    }
+
+    /// Fork any kind of stream into two stream like that the river branches.
     /// The closure being passed this function is called "router". Each item of original stream is
     /// passed to branch following to "router" decision.
     /// "Router" can return not only `Side` which is `Left` or `Right` but also
-    /// `bool` (`true` is considered as `Left`).
+    /// `bool` (`true` is considered as `Left`). To send an item to both branches or to neither,
+    /// return `Multicast`. It requires `Clone` items because `Both` sends a clone of the item to
+    /// each branch.
+    ///
+    /// An error of original stream is wrapped by `SharedError::Inner`. If another thread panics
+    /// while it polls original stream, branches return `SharedError::Poisoned` and then finish.
     ///
     /// # Examples
     ///
@@ -141,21 +525,615 @@ pub trait StreamExt: Stream {
     /// # fn main() {
     /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
     ///
-    /// let (even, odd) = rx.unsync_fork(|i| i % 2 == 0);
+    /// let (even, odd) = rx.fork(|i| i % 2 == 0);
     /// # }
     /// ```
-    ///
-    /// # Notice
-    ///
-    /// The value being returned by this function is not `Sync`. We will provide `Sync` version later.
-    fn unsync_fork<F, T>(self, router: F) -> (LeftUnsyncFork<Self, F>, RightUnsyncFork<Self, F>)
+    fn fork<F, T>(self, router: F) -> (LeftFork<Self, F>, RightFork<Self, F>)
     where
         Self: Sized,
         Self::Error: Clone,
         F: FnMut(&Self::Item) -> T,
-        Side: From<T>,
+        T: Route<Self::Item>,
     {
-        self::unsync_fork::unsync_fork(self, router)
+        self::fork::fork(self, router)
+    }
+
+
+    /// Fork any kind of stream into `n` streams.
+    /// "Router" returns the index of branch each item goes to. It can return not only `usize` but
+    /// also `Side`, `bool`, `Branches` to send an item to several branches, or your own type
+    /// implementing `Route` trait.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `n` is 0. If "router" returns an index out of range, it panics
+    /// while it holds original stream, so other branches return `SharedError::Poisoned`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// let stream = ::futures::stream::iter_ok::<_, ()>(0..6usize);
+    ///
+    /// let mut branches = stream.fork_n(3, |i| i % 3);
+    /// let rem2 = branches.pop().unwrap();
+    /// let rem1 = branches.pop().unwrap();
+    /// let rem0 = branches.pop().unwrap();
+    ///
+    /// assert_eq!(rem0.collect().wait(), Ok(vec![0, 3]));
+    /// assert_eq!(rem1.collect().wait(), Ok(vec![1, 4]));
+    /// assert_eq!(rem2.collect().wait(), Ok(vec![2, 5]));
+    /// # }
+    /// ```
+    fn fork_n<F, T>(self, n: usize, router: F) -> Vec<Fork<Self, F>>
+    where
+        Self: Sized,
+        Self::Error: Clone,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+    {
+        self::fork::fork_n(self, n, router)
+    }
+
+
+    /// Fork any kind of stream into two streams with bounded queues. Each branch holds at most
+    /// `capacity` items in its queue. While an item is routed to a full queue, original stream is
+    /// not polled and other branches wait until that branch reads its queue.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `capacity` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    ///
+    /// # fn main() {
+    /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
+    ///
+    /// // Each queue holds at most 16 items.
+    /// let (even, odd) = rx.fork_bounded(16, |i| i % 2 == 0);
+    /// # }
+    /// ```
+    fn fork_bounded<F, T>(
+        self,
+        capacity: usize,
+        router: F,
+    ) -> (LeftFork<Self, F>, RightFork<Self, F>)
+    where
+        Self: Sized,
+        Self::Error: Clone,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+    {
+        self::fork::fork_bounded(self, capacity, router)
+    }
+
+
+    /// Fork any kind of stream into `n` streams with bounded queues. Please have a look at
+    /// `fork_n` and `fork_bounded`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `n` or `capacity` is 0.
+    fn fork_n_bounded<F, T>(self, n: usize, capacity: usize, router: F) -> Vec<Fork<Self, F>>
+    where
+        Self: Sized,
+        Self::Error: Clone,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+    {
+        self::fork::fork_n_bounded(self, n, capacity, router)
+    }
+
+
+    /// Fork any kind of stream into two streams with an error policy which tells the branches an
+    /// error of original stream goes to. The policy can be `Broadcast` (the default of
+    /// `fork`), the index of branch which receives every error, or a closure which returns
+    /// `Route` like "router" does. Errors do not need `Clone` unless `Broadcast` or a `Route`
+    /// sending an error to several branches is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// #[derive(Debug, PartialEq)]
+    /// struct NotClone;
+    ///
+    /// let stream = ::futures::stream::iter_result(vec![Ok(0), Err(NotClone), Ok(1)]);
+    ///
+    /// // Every error goes to the right branch.
+    /// let (left, right) = stream.fork_with_errors(|_| true, 1);
+    ///
+    /// assert_eq!(left.collect().wait().unwrap(), [0, 1]);
+    /// # let _ = right;
+    /// # }
+    /// ```
+    fn fork_with_errors<F, T, G>(
+        self,
+        router: F,
+        errors: G,
+    ) -> (LeftFork<Self, F, G>, RightFork<Self, F, G>)
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+        G: RouteError<Self::Error>,
+    {
+        self::fork::fork_with_errors(self, router, errors)
+    }
+
+
+    /// Fork any kind of stream into `n` streams with an error policy. Please have a look at
+    /// `fork_n` and `fork_with_errors`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `n` is 0.
+    fn fork_n_with_errors<F, T, G>(
+        self,
+        n: usize,
+        router: F,
+        errors: G,
+    ) -> Vec<Fork<Self, F, G>>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+        G: RouteError<Self::Error>,
+    {
+        self::fork::fork_n_with_errors(self, n, router, errors)
+    }
+
+
+    /// Fork any kind of stream into `n` streams and a stream of its errors. Each error of
+    /// original stream is emitted as an item of the error stream instead of going to branches,
+    /// so errors do not need `Clone`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `n` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// let stream = ::futures::stream::iter_result(vec![Ok(0), Err("e"), Ok(1)]);
+    ///
+    /// let (mut branches, errors) = stream.fork_n_with_error_stream(1, |_| 0);
+    ///
+    /// assert_eq!(branches.pop().unwrap().collect().wait().unwrap(), [0, 1]);
+    /// assert_eq!(errors.collect().wait().unwrap(), ["e"]);
+    /// # }
+    /// ```
+    fn fork_n_with_error_stream<F, T>(
+        self,
+        n: usize,
+        router: F,
+    ) -> (Vec<ForkWithErrorStream<Self, F>>, ForkErrors<Self, F>)
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+    {
+        self::fork::fork_n_with_error_stream(self, n, router)
+    }
+
+
+    /// Fork any kind of stream into two "unsync" stream.
+    /// The closure being passed this function is called "router". Each item of original stream is
+    /// passed to branch following to "router" decision.
+    /// "Router" can return not only `Side` which is `Left` or `Right` but also
+    /// `bool` (`true` is considered as `Left`). To send an item to both branches or to neither,
+    /// return `Multicast`. It requires `Clone` items because `Both` sends a clone of the item to
+    /// each branch.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    ///
+    /// # fn main() {
+    /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
+    ///
+    /// let (even, odd) = rx.unsync_fork(|i| i % 2 == 0);
+    /// # }
+    /// ```
+    ///
+    /// # Notice
+    ///
+    /// The value being returned by this function is not `Sync`. We will provide `Sync` version later.
+    fn unsync_fork<F, T>(self, router: F) -> (LeftUnsyncFork<Self, F>, RightUnsyncFork<Self, F>)
+    where
+        Self: Sized,
+        Self::Error: Clone,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+    {
+        self::unsync_fork::unsync_fork(self, router)
+    }
+
+
+    /// Fork any kind of stream into `n` "unsync" streams.
+    /// "Router" returns the index of branch each item goes to. It can return not only `usize` but
+    /// also `Side`, `bool`, `Branches` to send an item to several branches, or your own type
+    /// implementing `Route` trait.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `n` is 0. Polling a branch panics if "router" returns an index
+    /// out of range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use ex_futures::stream::Route;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// enum Level {
+    ///     Info,
+    ///     Warn,
+    ///     Error,
+    /// }
+    ///
+    /// impl<T> Route<T> for Level {
+    ///     fn route<P: FnMut(usize, T)>(self, item: T, mut push: P) {
+    ///         push(self as usize, item)
+    ///     }
+    /// }
+    ///
+    /// let stream = ::futures::stream::iter_ok::<_, ()>(vec![0, 15, 30, 45]);
+    ///
+    /// let mut branches = stream.unsync_fork_n(3, |i| match *i {
+    ///     0..=9 => Level::Info,
+    ///     10..=29 => Level::Warn,
+    ///     _ => Level::Error,
+    /// });
+    /// let error = branches.pop().unwrap();
+    /// let warn = branches.pop().unwrap();
+    /// let info = branches.pop().unwrap();
+    ///
+    /// assert_eq!(info.collect().wait(), Ok(vec![0]));
+    /// assert_eq!(warn.collect().wait(), Ok(vec![15]));
+    /// assert_eq!(error.collect().wait(), Ok(vec![30, 45]));
+    /// # }
+    /// ```
+    fn unsync_fork_n<F, T>(self, n: usize, router: F) -> Vec<UnsyncFork<Self, F>>
+    where
+        Self: Sized,
+        Self::Error: Clone,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+    {
+        self::unsync_fork::unsync_fork_n(self, n, router)
+    }
+
+
+    /// Fork any kind of stream into two "unsync" streams with bounded queues. Each branch holds at
+    /// most `capacity` items in its queue. While an item is routed to a full queue, original
+    /// stream is not polled and other branches wait until that branch reads its queue.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `capacity` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    ///
+    /// # fn main() {
+    /// let (tx, rx) = ::futures::sync::mpsc::channel::<usize>(42);
+    ///
+    /// // Each queue holds at most 16 items.
+    /// let (even, odd) = rx.unsync_fork_bounded(16, |i| i % 2 == 0);
+    /// # }
+    /// ```
+    fn unsync_fork_bounded<F, T>(
+        self,
+        capacity: usize,
+        router: F,
+    ) -> (LeftUnsyncFork<Self, F>, RightUnsyncFork<Self, F>)
+    where
+        Self: Sized,
+        Self::Error: Clone,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+    {
+        self::unsync_fork::unsync_fork_bounded(self, capacity, router)
+    }
+
+
+    /// Fork any kind of stream into `n` "unsync" streams with bounded queues. Please have a look
+    /// at `unsync_fork_n` and `unsync_fork_bounded`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `n` or `capacity` is 0.
+    fn unsync_fork_n_bounded<F, T>(
+        self,
+        n: usize,
+        capacity: usize,
+        router: F,
+    ) -> Vec<UnsyncFork<Self, F>>
+    where
+        Self: Sized,
+        Self::Error: Clone,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+    {
+        self::unsync_fork::unsync_fork_n_bounded(self, n, capacity, router)
+    }
+
+
+    /// Fork any kind of stream into two "unsync" streams with an error policy which tells the
+    /// branches an error of original stream goes to. The policy can be `Broadcast` (the default
+    /// of `unsync_fork`), the index of branch which receives every error, or a closure which
+    /// returns `Route` like "router" does. Errors do not need `Clone` unless `Broadcast` or a
+    /// `Route` sending an error to several branches is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// #[derive(Debug, PartialEq)]
+    /// struct NotClone;
+    ///
+    /// let stream = ::futures::stream::iter_result(vec![Ok(0), Err(NotClone), Ok(1)]);
+    ///
+    /// // Every error goes to the right branch.
+    /// let (left, right) = stream.unsync_fork_with_errors(|_| true, 1);
+    ///
+    /// assert_eq!(left.collect().wait().unwrap(), [0, 1]);
+    /// # let _ = right;
+    /// # }
+    /// ```
+    fn unsync_fork_with_errors<F, T, G>(
+        self,
+        router: F,
+        errors: G,
+    ) -> (LeftUnsyncFork<Self, F, G>, RightUnsyncFork<Self, F, G>)
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+        G: RouteError<Self::Error>,
+    {
+        self::unsync_fork::unsync_fork_with_errors(self, router, errors)
+    }
+
+
+    /// Fork any kind of stream into `n` "unsync" streams with an error policy. Please have a look
+    /// at `unsync_fork_n` and `unsync_fork_with_errors`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `n` is 0.
+    fn unsync_fork_n_with_errors<F, T, G>(
+        self,
+        n: usize,
+        router: F,
+        errors: G,
+    ) -> Vec<UnsyncFork<Self, F, G>>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+        G: RouteError<Self::Error>,
+    {
+        self::unsync_fork::unsync_fork_n_with_errors(self, n, router, errors)
+    }
+
+
+    /// Fork any kind of stream into `n` "unsync" streams and a stream of its errors. Each error of
+    /// original stream is emitted as an item of the error stream instead of going to branches,
+    /// so errors do not need `Clone`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `n` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// let stream = ::futures::stream::iter_result(vec![Ok(0), Err("e"), Ok(1)]);
+    ///
+    /// let (mut branches, errors) = stream.unsync_fork_n_with_error_stream(1, |_| 0);
+    ///
+    /// assert_eq!(branches.pop().unwrap().collect().wait().unwrap(), [0, 1]);
+    /// assert_eq!(errors.collect().wait().unwrap(), ["e"]);
+    /// # }
+    /// ```
+    fn unsync_fork_n_with_error_stream<F, T>(
+        self,
+        n: usize,
+        router: F,
+    ) -> (Vec<UnsyncForkWithErrorStream<Self, F>>, UnsyncForkErrors<Self, F>)
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> T,
+        T: Route<Self::Item>,
+    {
+        self::unsync_fork::unsync_fork_n_with_error_stream(self, n, router)
+    }
+
+
+    /// Split any kind of stream into two streams whose items have different types.
+    /// The closure maps each item into `Either::Left` which goes to the left stream, or
+    /// `Either::Right` which goes to the right stream. Both streams share original stream like
+    /// `fork`.
+    ///
+    /// An error of original stream is wrapped by `SharedError::Inner` and passed to both streams.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use ex_futures::stream::Either;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// let stream = ::futures::stream::iter_ok::<_, ()>(vec!["1", "a", "2"]);
+    ///
+    /// let (numbers, words) = stream.split_either(|s| match s.parse::<usize>() {
+    ///     Ok(n) => Either::Left(n),
+    ///     Err(_) => Either::Right(s.to_string()),
+    /// });
+    ///
+    /// assert_eq!(numbers.collect().wait(), Ok(vec![1, 2]));
+    /// assert_eq!(words.collect().wait(), Ok(vec!["a".to_string()]));
+    /// # }
+    /// ```
+    fn split_either<F, L, R>(self, f: F) -> SplitEither<Self, F>
+    where
+        Self: Sized,
+        Self::Error: Clone,
+        F: FnMut(Self::Item) -> Either<L, R>,
+    {
+        self::split::split(self.map(f))
+    }
+
+
+    /// Split stream of `Result` items into stream of `Ok` values and stream of `Err` values.
+    /// Please have a look at `split_either`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// let stream = ::futures::stream::iter_ok::<_, ()>(vec![Ok(0), Err("e"), Ok(1)]);
+    ///
+    /// let (oks, errs) = stream.partition_result();
+    ///
+    /// assert_eq!(oks.collect().wait(), Ok(vec![0, 1]));
+    /// assert_eq!(errs.collect().wait(), Ok(vec!["e"]));
+    /// # }
+    /// ```
+    fn partition_result<T, E>(self) -> (SplitLeft<Self>, SplitRight<Self>)
+    where
+        Self: Stream<Item = Result<T, E>> + Sized,
+        Self::Error: Clone,
+    {
+        self::split::split(self)
+    }
+
+
+    /// Split stream of pairs into stream of first values and stream of second values.
+    /// Please have a look at `split_either`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// let stream = ::futures::stream::iter_ok::<_, ()>(vec![(0, "a"), (1, "b")]);
+    ///
+    /// let (numbers, letters) = stream.unzip();
+    ///
+    /// assert_eq!(numbers.collect().wait(), Ok(vec![0, 1]));
+    /// assert_eq!(letters.collect().wait(), Ok(vec!["a", "b"]));
+    /// # }
+    /// ```
+    fn unzip<A, B>(self) -> (SplitLeft<Self>, SplitRight<Self>)
+    where
+        Self: Stream<Item = (A, B)> + Sized,
+        Self::Error: Clone,
+    {
+        self::split::split(self)
+    }
+
+
+    /// Split any kind of stream into groups of items having the same key.
+    /// Returned stream emits a pair of a key and a `Group`, which is a stream of items having
+    /// that key, whenever a new key appears. Every group finishes when original stream finishes.
+    ///
+    /// An error of original stream is passed to every group and to returned stream.
+    /// If a group is dropped, its queued items are discarded, and the next item having its key
+    /// starts a new group.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate ex_futures;
+    /// use ex_futures::StreamExt;
+    /// use futures::{Future, Stream};
+    ///
+    /// # fn main() {
+    /// let events = ::futures::stream::iter_ok::<_, ()>(vec![("a", 1), ("b", 2), ("a", 3)]);
+    ///
+    /// let groups = events.group_by(|&(name, _)| name).collect().wait().unwrap();
+    ///
+    /// let mut groups = groups.into_iter();
+    /// let (a, group_a) = groups.next().unwrap();
+    /// let (b, group_b) = groups.next().unwrap();
+    ///
+    /// assert_eq!(a, "a");
+    /// assert_eq!(group_a.collect().wait(), Ok(vec![("a", 1), ("a", 3)]));
+    /// assert_eq!(b, "b");
+    /// assert_eq!(group_b.collect().wait(), Ok(vec![("b", 2)]));
+    /// # }
+    /// ```
+    ///
+    /// # Notice
+    ///
+    /// The value being returned by this function is not `Sync`.
+    fn group_by<F, K>(self, key_fn: F) -> GroupBy<Self, F, K>
+    where
+        Self: Sized,
+        Self::Error: Clone,
+        F: FnMut(&Self::Item) -> K,
+        K: Eq + Hash + Clone,
+    {
+        self::group_by::group_by(self, key_fn)
     }
 
 
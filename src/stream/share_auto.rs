@@ -0,0 +1,111 @@
+use futures::Stream;
+
+use super::cloneable::{cloneable, Cloneable, WeakCloneable};
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+
+/// Creates `ShareAuto` which creates original stream by `factory` when it is needed.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate ex_futures;
+/// use ex_futures::stream::share_auto;
+/// use futures::{Future, Stream};
+///
+/// # fn main() {
+/// // Opens an expensive feed only while somebody subscribes it.
+/// let feed = share_auto(|| ::futures::stream::iter_ok::<_, ()>(0..3));
+///
+/// let rx = feed.subscribe(); // Creates original stream.
+/// assert_eq!(rx.collect().wait(), Ok(vec![0, 1, 2])); // Drops original stream at last.
+///
+/// let rx = feed.subscribe(); // Creates original stream again.
+/// assert_eq!(rx.collect().wait(), Ok(vec![0, 1, 2]));
+/// # }
+/// ```
+pub fn share_auto<S, F>(factory: F) -> ShareAuto<S, F>
+where
+    S: Stream,
+    F: FnMut() -> S,
+{
+    let inner = Inner {
+        factory: factory,
+        current: None,
+    };
+
+    ShareAuto { inner: Arc::new(Mutex::new(inner)) }
+}
+
+
+struct Inner<S: Stream, F> {
+    factory: F,
+    // Weak handle of original stream which is currently shared.
+    current: Option<WeakCloneable<S>>,
+}
+
+
+/// A source of `Cloneable` streams being created by `share_auto` function.
+///
+/// Original stream is created by the factory when the first subscriber appears, and is dropped
+/// when every subscriber is dropped. The next subscriber creates it again. It is also created
+/// again when it has finished, even if old subscribers are still living.
+///
+/// `ShareAuto` is cheap to clone and every clone shares the same original stream.
+pub struct ShareAuto<S: Stream, F> {
+    inner: Arc<Mutex<Inner<S, F>>>,
+}
+
+
+
+impl<S, F> ShareAuto<S, F>
+where
+    S: Stream,
+    S::Item: Clone,
+    S::Error: Clone,
+    F: FnMut() -> S,
+{
+    /// Creates a new subscriber which reads items arriving after now.
+    /// This creates original stream if no subscriber is living or it has finished.
+    pub fn subscribe(&self) -> Cloneable<S> {
+        let mut inner = lock_inner(&self.inner);
+
+        if let Some(rx) = inner.current.as_ref().and_then(WeakCloneable::upgrade) {
+            if !rx.is_finished() {
+                return rx;
+            }
+            // Old subscribers are still living, but original stream has already finished.
+        }
+
+        let rx = cloneable((inner.factory)());
+        inner.current = Some(rx.downgrade());
+        rx
+    }
+}
+
+
+fn lock_inner<S: Stream, F>(inner: &Mutex<Inner<S, F>>) -> MutexGuard<'_, Inner<S, F>> {
+    match inner.lock() {
+        Ok(inner) => inner,
+        // Even if the factory panicked, next subscriber just calls it again.
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+
+
+impl<S: Stream, F> Clone for ShareAuto<S, F> {
+    fn clone(&self) -> Self {
+        ShareAuto { inner: self.inner.clone() }
+    }
+}
+
+
+
+impl<S: Stream, F> ::std::fmt::Debug for ShareAuto<S, F> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "ShareAuto(..)")
+    }
+}
@@ -0,0 +1,235 @@
+use futures::{Stream, Poll, Async};
+
+use error::SharedError;
+use super::fork::{self, Fork};
+
+
+/// Split given stream into two streams with different item types. Please have a look at document
+/// of `StreamExt` trait.
+pub fn split<S>(stream: S) -> (SplitLeft<S>, SplitRight<S>)
+where
+    S: Stream,
+    S::Item: Split,
+{
+    let halves = Halves {
+        stream: stream,
+        right: None,
+    };
+
+    let (left, right) = fork::fork(halves, Either::is_left as Router<S>);
+    (SplitLeft { fork: left }, SplitRight { fork: right })
+}
+
+
+
+/// A value which is either `Left` or `Right`. "Router" of `split_either` returns this.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+
+impl<L, R> Either<L, R> {
+    /// Returns `true` if this is `Left`.
+    pub fn is_left(&self) -> bool {
+        match *self {
+            Either::Left(_) => true,
+            Either::Right(_) => false,
+        }
+    }
+
+    /// Returns `true` if this is `Right`.
+    pub fn is_right(&self) -> bool {
+        !self.is_left()
+    }
+}
+
+
+
+/// An item which can be split into a part for the left stream and a part for the right stream.
+///
+/// This is implemented for `Either` (one of the parts), `Result` (`Ok` is left and `Err` is
+/// right) and pairs (both parts).
+pub trait Split {
+    type Left;
+    type Right;
+
+    /// Returns the parts of this item. A missing part is not passed to its stream.
+    fn split(self) -> (Option<Self::Left>, Option<Self::Right>);
+}
+
+impl<L, R> Split for Either<L, R> {
+    type Left = L;
+    type Right = R;
+
+    fn split(self) -> (Option<L>, Option<R>) {
+        match self {
+            Either::Left(l) => (Some(l), None),
+            Either::Right(r) => (None, Some(r)),
+        }
+    }
+}
+
+impl<T, E> Split for Result<T, E> {
+    type Left = T;
+    type Right = E;
+
+    fn split(self) -> (Option<T>, Option<E>) {
+        match self {
+            Ok(t) => (Some(t), None),
+            Err(e) => (None, Some(e)),
+        }
+    }
+}
+
+impl<A, B> Split for (A, B) {
+    type Left = A;
+    type Right = B;
+
+    fn split(self) -> (Option<A>, Option<B>) {
+        (Some(self.0), Some(self.1))
+    }
+}
+
+
+type Left<S> = <<S as Stream>::Item as Split>::Left;
+type Right<S> = <<S as Stream>::Item as Split>::Right;
+type Router<S> = fn(&Either<Left<S>, Right<S>>) -> bool;
+
+
+
+/// Original stream whose items are split into `Either` values. The right part of an item is
+/// emitted just after its left part.
+struct Halves<S>
+where
+    S: Stream,
+    S::Item: Split,
+{
+    stream: S,
+    right: Option<Right<S>>,
+}
+
+
+impl<S> Stream for Halves<S>
+where
+    S: Stream,
+    S::Item: Split,
+{
+    type Item = Either<Left<S>, Right<S>>;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, S::Error> {
+        if let Some(right) = self.right.take() {
+            return Ok(Async::Ready(Some(Either::Right(right))));
+        }
+
+        loop {
+            let item = match try_ready!(self.stream.poll()) {
+                Some(item) => item,
+                None => return Ok(Async::Ready(None)),
+            };
+
+            match item.split() {
+                (Some(left), right) => {
+                    self.right = right;
+                    return Ok(Async::Ready(Some(Either::Left(left))));
+                }
+                (None, Some(right)) => return Ok(Async::Ready(Some(Either::Right(right)))),
+                (None, None) => (),
+            }
+        }
+    }
+}
+
+
+
+/// The left stream of `split_either`, `partition_result` or `unzip`.
+///
+/// An error of original stream is wrapped by `SharedError::Inner` and passed to both streams.
+pub struct SplitLeft<S>
+where
+    S: Stream,
+    S::Item: Split,
+{
+    fork: Fork<Halves<S>, Router<S>>,
+}
+
+
+impl<S> Stream for SplitLeft<S>
+where
+    S: Stream,
+    S::Item: Split,
+    S::Error: Clone,
+{
+    type Item = Left<S>;
+    type Error = SharedError<S::Error>;
+
+    fn poll(&mut self) -> Poll<Option<Left<S>>, SharedError<S::Error>> {
+        match try_ready!(self.fork.poll()) {
+            Some(Either::Left(item)) => Ok(Async::Ready(Some(item))),
+            Some(Either::Right(_)) => unreachable!("right part is routed to the left stream"),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+
+impl<S> ::std::fmt::Debug for SplitLeft<S>
+where
+    S: Stream,
+    S::Item: Split,
+{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        f.debug_struct("SplitLeft")
+            .field("queue_len", &self.fork.queue_len())
+            .field("terminated", &self.fork.is_terminated())
+            .finish()
+    }
+}
+
+
+
+/// The right stream of `split_either`, `partition_result` or `unzip`.
+///
+/// An error of original stream is wrapped by `SharedError::Inner` and passed to both streams.
+pub struct SplitRight<S>
+where
+    S: Stream,
+    S::Item: Split,
+{
+    fork: Fork<Halves<S>, Router<S>>,
+}
+
+
+impl<S> Stream for SplitRight<S>
+where
+    S: Stream,
+    S::Item: Split,
+    S::Error: Clone,
+{
+    type Item = Right<S>;
+    type Error = SharedError<S::Error>;
+
+    fn poll(&mut self) -> Poll<Option<Right<S>>, SharedError<S::Error>> {
+        match try_ready!(self.fork.poll()) {
+            Some(Either::Right(item)) => Ok(Async::Ready(Some(item))),
+            Some(Either::Left(_)) => unreachable!("left part is routed to the right stream"),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+
+impl<S> ::std::fmt::Debug for SplitRight<S>
+where
+    S: Stream,
+    S::Item: Split,
+{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        f.debug_struct("SplitRight")
+            .field("queue_len", &self.fork.queue_len())
+            .field("terminated", &self.fork.is_terminated())
+            .finish()
+    }
+}
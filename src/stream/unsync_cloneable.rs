@@ -1,44 +1,306 @@
 use futures::{Stream, Poll, Async};
+use futures::task::{self, Task};
+
+use util::{Slab, SharedCell};
+use super::DEFAULT_BUDGET;
 
 use std::rc::{Rc, Weak};
 use std::collections::VecDeque;
-use std::cell::RefCell;
 
 
 /// Convert given stream into `UnsyncCloneable`.
 /// `UnsyncCloneable` is able to be cloned.
 pub fn unsync_cloneable<S: Stream>(stream: S) -> UnsyncCloneable<S> {
-    let queue = Rc::new(RefCell::new(VecDeque::new()));
+    new(stream, None, 0, None)
+}
 
-    let receivers = vec![Rc::downgrade(&queue)];
 
-    let shared = Shared {
-        stream: stream,
-        receivers: receivers,
-    };
+/// Convert given stream into `UnsyncCloneable` whose queues have limited capacity.
+pub fn unsync_cloneable_bounded<S: Stream>(stream: S, capacity: usize) -> UnsyncCloneable<S> {
+    assert!(capacity > 0, "capacity of cloneable stream must be positive");
+    new(stream, Some(capacity), 0, None)
+}
+
+
+/// Convert given stream into `UnsyncCloneable` which keeps every item for clones created later.
+pub fn unsync_cached<S>(stream: S) -> UnsyncCloneable<S>
+where
+    S: Stream,
+    S::Item: Clone,
+    S::Error: Clone,
+{
+    new(stream, None, usize::MAX, Some(clone_items))
+}
+
+
+/// Convert given stream into `UnsyncCloneable` which keeps last `n` items for clones created
+/// later.
+pub fn unsync_replayable<S>(stream: S, n: usize) -> UnsyncCloneable<S>
+where
+    S: Stream,
+    S::Item: Clone,
+    S::Error: Clone,
+{
+    new(stream, None, n, Some(clone_items))
+}
+
+
+/// Convert given stream into `UnsyncConnectable`.
+/// Original stream is not polled until `UnsyncConnectable::connect` is called.
+pub fn unsync_publish<S: Stream>(stream: S) -> UnsyncConnectable<S> {
+    let shared = new_shared(stream, None, 0, None, Some(0));
+    UnsyncConnectable { shared: Rc::new(SharedCell::new(shared)) }
+}
+
+
+fn new<S: Stream>(
+    stream: S,
+    capacity: Option<usize>,
+    history: usize,
+    clone_history: Option<CloneItems<S::Item, S::Error>>,
+) -> UnsyncCloneable<S> {
+    let mut shared = new_shared(stream, capacity, history, clone_history, None);
+    let key = shared.receivers.insert(Receiver::new(VecDeque::new(), None));
 
     UnsyncCloneable {
-        queue: queue,
-        shared: Rc::new(RefCell::new(shared)),
+        key: key,
+        shared: Rc::new(SharedCell::new(shared)),
+        budget: DEFAULT_BUDGET,
     }
 }
 
 
-struct Shared<S: Stream> {
+fn new_shared<S: Stream>(
     stream: S,
-    receivers: Vec<Weak<Queue<S>>>,
+    capacity: Option<usize>,
+    history: usize,
+    clone_history: Option<CloneItems<S::Item, S::Error>>,
+    connections: Option<usize>,
+) -> Shared<S> {
+    Shared {
+        stream: Some(stream),
+        receivers: Slab::new(),
+        capacity: capacity,
+        blocked: Vec::new(),
+        connections: connections,
+        has_connectable: connections.is_some(),
+        history: VecDeque::new(),
+        history_len: history,
+        history_items: 0,
+        clone_history: clone_history,
+        budget: DEFAULT_BUDGET,
+        dispatched: 0,
+        terminated: false,
+    }
+}
+
+
+type Items<T, E> = VecDeque<Result<Option<T>, E>>;
+
+type CloneItems<T, E> = fn(&Items<T, E>) -> Items<T, E>;
+
+
+fn clone_items<T: Clone, E: Clone>(items: &Items<T, E>) -> Items<T, E> {
+    items.clone()
 }
 
 
-type Queue<S: Stream> = RefCell<VecDeque<Result<Option<S::Item>, S::Error>>>;
+struct Shared<S: Stream> {
+    // `None` after original stream finishes.
+    stream: Option<S>,
+    // Queues of living clones. Each clone owns the index of its queue.
+    receivers: Slab<Receiver<S::Item, S::Error>>,
+    capacity: Option<usize>,
+    // Tasks waiting for some queue to have space or for a connection.
+    blocked: Vec<Task>,
+    // Number of living `UnsyncConnection`s. `None` unless this is created by `unsync_publish`
+    // function.
+    connections: Option<usize>,
+    // Whether `UnsyncConnectable` is living.
+    has_connectable: bool,
+    // Last `history_len` items for clones created later, with errors and the end following them.
+    history: Items<S::Item, S::Error>,
+    history_len: usize,
+    // Number of items in `history`. Errors and the end are not counted.
+    history_items: usize,
+    // Copies `history` for a new clone. Only streams keeping items have this, so a clone of
+    // other streams does not need `Clone` items.
+    clone_history: Option<CloneItems<S::Item, S::Error>>,
+    // Budget of clones created by `WeakUnsyncCloneable::upgrade` and
+    // `UnsyncConnectable::subscribe`. This is the budget which is set last.
+    budget: usize,
+    // Number of items which original stream has produced.
+    dispatched: u64,
+    terminated: bool,
+}
+
+
+impl<S: Stream> Shared<S> {
+    /// Adds a message to the history. History starts at the `history_len`-th last item, so errors
+    /// and the end are kept with the items around them, but they are not counted.
+    fn keep(&mut self, msg: Result<Option<S::Item>, S::Error>) {
+        if self.history_len == 0 {
+            return;
+        }
+
+        if let Ok(Some(_)) = msg {
+            self.history_items += 1;
+        }
+        self.history.push_back(msg);
+
+        loop {
+            let is_item = match self.history.front() {
+                Some(&Ok(Some(_))) => true,
+                Some(_) => false,
+                None => break,
+            };
+            let outside = if is_item {
+                self.history_items > self.history_len
+            } else {
+                self.history_items >= self.history_len
+            };
+            if !outside {
+                break;
+            }
+            self.history.pop_front();
+            if is_item {
+                self.history_items -= 1;
+            }
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        match self.capacity {
+            Some(capacity) => {
+                // Lossy clones never throttle original stream.
+                self.receivers.iter().any(|rx| {
+                    rx.lossy.is_none() && rx.items.len() >= capacity
+                })
+            }
+            None => false,
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connections.map(|n| n > 0).unwrap_or(true)
+    }
+
+    /// Returns `true` while somebody is able to read items from original stream.
+    fn is_alive(&self) -> bool {
+        !self.receivers.is_empty() || self.has_connectable
+    }
+
+    fn remove(&mut self, handle: Handle) {
+        match handle {
+            Handle::Cloneable(key) => {
+                self.receivers.remove(key);
+                // Other clones may be waiting for this queue to have space.
+                self.notify_blocked();
+                // This clone may be the one which original stream will notify. So we notify
+                // others instead.
+                self.notify_parked();
+            }
+            Handle::Connectable => self.has_connectable = false,
+            Handle::Connection => {
+                if let Some(ref mut connections) = self.connections {
+                    *connections -= 1;
+                }
+            }
+        }
+    }
+
+    fn notify_blocked(&mut self) {
+        for task in self.blocked.drain(..) {
+            task.notify();
+        }
+    }
+
+    fn notify_parked(&mut self) {
+        for rx in self.receivers.iter_mut() {
+            notify(&mut rx.task);
+        }
+    }
+}
+
+
+/// A handle which is dropped while `Shared` is borrowed.
+enum Handle {
+    Cloneable(usize),
+    Connectable,
+    Connection,
+}
+
+
+struct Receiver<T, E> {
+    items: VecDeque<Result<Option<T>, E>>,
+    // Capacity of a lossy clone.
+    lossy: Option<usize>,
+    // Number of items which this lossy clone has dropped.
+    dropped: u64,
+    // Task waiting for a new item in this queue.
+    task: Option<Task>,
+}
+
+
+impl<T, E> Receiver<T, E> {
+    fn new(items: VecDeque<Result<Option<T>, E>>, lossy: Option<usize>) -> Receiver<T, E> {
+        let mut rx = Receiver {
+            items: items,
+            lossy: lossy,
+            dropped: 0,
+            task: None,
+        };
+        rx.drop_overflow();
+        rx
+    }
+
+    fn push(&mut self, msg: Result<Option<T>, E>) {
+        self.items.push_back(msg);
+        self.drop_overflow();
+        notify(&mut self.task);
+    }
+
+    /// Drops the oldest items while this lossy clone is full.
+    fn drop_overflow(&mut self) {
+        if let Some(capacity) = self.lossy {
+            while self.items.len() > capacity {
+                self.items.pop_front();
+                self.dropped += 1;
+            }
+        }
+    }
+}
+
+
+fn notify(task: &mut Option<Task>) {
+    if let Some(task) = task.take() {
+        task.notify();
+    }
+}
 
 
 /// A cloneable stream being created by `unsync_cloneable` function.
 /// You can `clone` this stream as you want.
 /// Each cloned stream is also cloneable.
+///
+/// If this stream is created by `unsync_cloneable_bounded` function, original stream is not
+/// polled while any queue is full. So the slowest clone throttles others.
+///
+/// A clone created by `clone_lossy` function never throttles original stream. Instead it drops
+/// its oldest item when its queue is full.
+///
+/// If this stream is created by `unsync_cached` or `unsync_replayable` function, a new clone
+/// reads kept items at first.
+///
+/// If this stream is created by `UnsyncConnectable::subscribe` function, original stream is
+/// polled only while it is connected.
+///
+/// Original stream is dropped as soon as it finishes or every clone is dropped.
 pub struct UnsyncCloneable<S: Stream> {
-    queue: Rc<Queue<S>>,
-    shared: Rc<RefCell<Shared<S>>>,
+    key: usize,
+    shared: Rc<SharedCell<Shared<S>, Handle>>,
+    // Maximum number of times one poll of this clone polls original stream.
+    budget: usize,
 }
 
 
@@ -53,35 +315,84 @@ where
     type Error = S::Error;
 
     fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
-        // Check self queue
-        match self.queue.borrow_mut().pop_front() { // Never panics because this is unsync.
-            Some(Ok(Some(msg))) => return Ok(Async::Ready(Some(msg))),
-            Some(Ok(None)) => return Ok(Async::Ready(None)),
-            Some(Err(e)) => return Err(e),
-            None => (),
-        }
+        let mut shared = self.shared.borrow_mut(); // Never panics because this is unsync.
+        let mut polled = 0;
+
+        loop {
+            remove_dropped(&mut shared, &self.shared);
+
+            // Check self queue
+            let msg = match shared.receivers.get_mut(self.key) {
+                Some(rx) => {
+                    let msg = rx.items.pop_front();
+                    if msg.is_none() {
+                        // Another clone may push a new item before this clone polls again.
+                        rx.task = Some(task::current());
+                    }
+                    msg
+                }
+                None => None,
+            };
+            if msg.is_some() {
+                // Now this queue has space.
+                shared.notify_blocked();
+            }
+
+            match msg {
+                Some(Ok(Some(msg))) => return Ok(Async::Ready(Some(msg))),
+                Some(Ok(None)) => return Ok(Async::Ready(None)),
+                Some(Err(e)) => return Err(e),
+                None => (),
+            }
+
+            if polled == self.budget {
+                // Original stream keeps producing items without giving one to this clone.
+                // Yield to other tasks, and poll it again soon.
+                task::current().notify();
+                return Ok(Async::NotReady);
+            }
+            polled += 1;
+
+            // Stop polling original stream while any queue is full or it is not connected.
+            if shared.is_full() || !shared.is_connected() {
+                shared.blocked.push(task::current());
+                return Ok(Async::NotReady);
+            }
 
-        {
-            let mut shared = self.shared.borrow_mut();
-            let poll = shared.stream.poll();
+            let poll = match shared.stream.as_mut() {
+                Some(stream) => stream.poll(),
+                // Original stream has already finished and this clone was created after that.
+                None => return Ok(Async::Ready(None)),
+            };
+            // Original stream may drop clones.
+            remove_dropped(&mut shared, &self.shared);
 
             let msg = match poll {
                 Err(e) => Err(e.clone()),
-                Ok(Async::Ready(Some(msg))) => Ok(Some(msg)),
-                Ok(Async::Ready(None)) => Ok(None),
+                Ok(Async::Ready(Some(msg))) => {
+                    shared.dispatched += 1;
+                    Ok(Some(msg))
+                }
+                Ok(Async::Ready(None)) => {
+                    shared.terminated = true;
+                    // Release resources of original stream right now.
+                    shared.stream = None;
+                    Ok(None)
+                }
                 Ok(Async::NotReady) => {
                     return Ok(Async::NotReady);
                 }
             };
 
-            // Do not remove outdated receivers here because of performance.
-            // We do it at `UnsyncCloneable::clone` function.
-            for rx in shared.receivers.iter().filter_map(Weak::upgrade) {
-                rx.borrow_mut().push_back(msg.clone()); // Never panics because this is unsync.
+            if let Some(rx) = shared.receivers.get_mut(self.key) {
+                rx.task = None; // We are going to read it right now.
+            }
+            for rx in shared.receivers.iter_mut() {
+                rx.push(msg.clone());
             }
-        }
 
-        self.poll()
+            shared.keep(msg);
+        }
     }
 }
 
@@ -89,24 +400,315 @@ where
 
 impl<S: Stream> Clone for UnsyncCloneable<S> {
     fn clone(&self) -> Self {
-        let queue = Rc::new(RefCell::new(VecDeque::new()));
+        let lossy = self.receiver(|rx| rx.lossy);
+        subscribe(&self.shared, lossy, self.budget)
+    }
+}
 
-        let mut shared = self.shared.borrow_mut();
-        shared.receivers.retain(|weak| weak.upgrade().is_some()); // Remove outdated receivers.
-        shared.receivers.push(Rc::downgrade(&queue));
-        drop(shared);
 
-        UnsyncCloneable {
-            queue: queue,
-            shared: self.shared.clone(),
+
+impl<S> UnsyncCloneable<S>
+where
+    S: Stream,
+    S::Item: Clone,
+    S::Error: Clone,
+{
+    /// Creates a lossy clone whose queue holds at most `capacity` items.
+    /// It never throttles original stream, and drops its oldest item when its queue is full.
+    /// Clones of a lossy clone are also lossy.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `capacity` is 0.
+    pub fn clone_lossy(&self, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity of lossy clone must be positive");
+        subscribe(&self.shared, Some(capacity), self.budget)
+    }
+}
+
+
+/// Creates a new clone which reads kept items at first.
+fn subscribe<S: Stream>(
+    shared: &Rc<SharedCell<Shared<S>, Handle>>,
+    lossy: Option<usize>,
+    budget: usize,
+) -> UnsyncCloneable<S> {
+    let key = {
+        let mut shared = shared.borrow_mut();
+        let items = match shared.clone_history {
+            Some(clone_history) => clone_history(&shared.history),
+            None => VecDeque::new(),
+        };
+        let rx = Receiver::new(items, lossy);
+        shared.receivers.insert(rx)
+    };
+
+    UnsyncCloneable {
+        key: key,
+        shared: shared.clone(),
+        budget: budget,
+    }
+}
+
+
+
+impl<S: Stream> Drop for UnsyncCloneable<S> {
+    fn drop(&mut self) {
+        // Original stream may drop a clone while it is polled. Then the poller removes its queue.
+        let handle = Handle::Cloneable(self.key);
+        if let Some((mut shared, handle)) = self.shared.borrow_or_defer(handle) {
+            shared.remove(handle);
+
+            // Items of this queue may hold clones.
+            remove_dropped(&mut shared, &self.shared);
         }
     }
 }
 
 
+/// Removes handles which are dropped while `Shared` is borrowed.
+fn remove_dropped<S: Stream>(shared: &mut Shared<S>, cell: &SharedCell<Shared<S>, Handle>) {
+    loop {
+        let dropped = cell.take_dropped();
+        if dropped.is_empty() {
+            return;
+        }
+
+        for handle in dropped {
+            shared.remove(handle);
+        }
+    }
+}
+
+
+
+impl<S: Stream> UnsyncCloneable<S> {
+    /// Returns the number of living clones including this one.
+    pub fn clone_count(&self) -> usize {
+        self.shared.borrow().receivers.len()
+    }
+
+    /// Returns the number of items which this clone has not read yet.
+    pub fn queue_len(&self) -> usize {
+        self.receiver(|rx| rx.items.len())
+    }
+
+    /// Returns `true` if this clone is created by `clone_lossy` function.
+    pub fn is_lossy(&self) -> bool {
+        self.receiver(|rx| rx.lossy.is_some())
+    }
+
+    /// Returns the number of items which this lossy clone has dropped.
+    pub fn dropped(&self) -> u64 {
+        self.receiver(|rx| rx.dropped)
+    }
+
+    /// Returns `true` if original stream has finished.
+    pub fn is_terminated(&self) -> bool {
+        self.shared.borrow().terminated
+    }
+
+    /// Returns the number of items which original stream has produced.
+    pub fn dispatched(&self) -> u64 {
+        self.shared.borrow().dispatched
+    }
+
+    /// Sets the maximum number of times one poll of this clone polls original stream without
+    /// getting an item. After that, the poll yields to other tasks by notifying current task and
+    /// returning `NotReady`. The default is 32. Clones of this clone and clones created later by
+    /// `WeakUnsyncCloneable::upgrade` or `UnsyncConnectable::subscribe` inherit it.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `budget` is 0.
+    pub fn set_budget(&mut self, budget: usize) {
+        assert!(budget > 0, "budget must be positive");
+        self.budget = budget;
+        self.shared.borrow_mut().budget = budget;
+    }
+
+    /// Creates a weak handle which does not consume items.
+    /// You can create a new clone from it while any clone is living.
+    pub fn downgrade(&self) -> WeakUnsyncCloneable<S> {
+        WeakUnsyncCloneable { shared: Rc::downgrade(&self.shared) }
+    }
+
+    fn receiver<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&Receiver<S::Item, S::Error>) -> T,
+        T: Default,
+    {
+        self.shared
+            .borrow()
+            .receivers
+            .get(self.key)
+            .map(f)
+            .unwrap_or_default()
+    }
+}
+
+
 
 impl<S: Stream> ::std::fmt::Debug for UnsyncCloneable<S> {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-        write!(f, "UnsyncCloneable(..)")
+        f.debug_struct("UnsyncCloneable")
+            .field("clone_count", &self.clone_count())
+            .field("queue_len", &self.queue_len())
+            .field("terminated", &self.is_terminated())
+            .field("dispatched", &self.dispatched())
+            .field("budget", &self.budget)
+            .finish()
+    }
+}
+
+
+
+/// A weak handle of `UnsyncCloneable` stream being created by `UnsyncCloneable::downgrade`
+/// function.
+///
+/// This handle does not read items, so items are never accumulated for it. And it does not keep
+/// original stream alive.
+pub struct WeakUnsyncCloneable<S: Stream> {
+    shared: Weak<SharedCell<Shared<S>, Handle>>,
+}
+
+
+
+impl<S> WeakUnsyncCloneable<S>
+where
+    S: Stream,
+    S::Item: Clone,
+    S::Error: Clone,
+{
+    /// Creates a new clone if any clone is living.
+    /// The new clone reads items arriving after now, or kept items if the stream is created by
+    /// `unsync_cached` or `unsync_replayable` function.
+    pub fn upgrade(&self) -> Option<UnsyncCloneable<S>> {
+        let shared = self.shared.upgrade()?;
+        let budget = {
+            let shared = shared.borrow();
+            if !shared.is_alive() {
+                // Original stream is already released.
+                return None;
+            }
+            shared.budget
+        };
+        Some(subscribe(&shared, None, budget))
+    }
+}
+
+
+
+impl<S: Stream> Clone for WeakUnsyncCloneable<S> {
+    fn clone(&self) -> Self {
+        WeakUnsyncCloneable { shared: self.shared.clone() }
+    }
+}
+
+
+
+impl<S: Stream> ::std::fmt::Debug for WeakUnsyncCloneable<S> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "WeakUnsyncCloneable(..)")
+    }
+}
+
+
+
+/// A stream source being created by `unsync_publish` function.
+///
+/// You can create any number of clones by `subscribe` function. Original stream is not polled
+/// until `connect` is called, so every clone created before that reads every item.
+pub struct UnsyncConnectable<S: Stream> {
+    shared: Rc<SharedCell<Shared<S>, Handle>>,
+}
+
+
+
+impl<S: Stream> UnsyncConnectable<S> {
+    /// Creates a new clone which reads items arriving after now.
+    pub fn subscribe(&self) -> UnsyncCloneable<S>
+    where
+        S::Item: Clone,
+        S::Error: Clone,
+    {
+        let budget = self.shared.borrow().budget;
+        subscribe(&self.shared, None, budget)
+    }
+
+    /// Sets the budget of clones created after this call. Please have a look at `set_budget` of
+    /// `UnsyncCloneable`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `budget` is 0.
+    pub fn set_budget(&self, budget: usize) {
+        assert!(budget > 0, "budget must be positive");
+        self.shared.borrow_mut().budget = budget;
+    }
+
+    /// Starts polling original stream. It stops when every returned `UnsyncConnection` is
+    /// dropped.
+    pub fn connect(&self) -> UnsyncConnection<S> {
+        let mut shared = self.shared.borrow_mut();
+        if let Some(ref mut connections) = shared.connections {
+            *connections += 1;
+        }
+        // Clones waiting for a connection.
+        shared.notify_blocked();
+
+        UnsyncConnection { shared: Rc::downgrade(&self.shared) }
+    }
+}
+
+
+
+impl<S: Stream> Drop for UnsyncConnectable<S> {
+    fn drop(&mut self) {
+        if let Some((mut shared, handle)) = self.shared.borrow_or_defer(Handle::Connectable) {
+            shared.remove(handle);
+        }
+    }
+}
+
+
+
+impl<S: Stream> ::std::fmt::Debug for UnsyncConnectable<S> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        let shared = self.shared.borrow();
+        f.debug_struct("UnsyncConnectable")
+            .field("clone_count", &shared.receivers.len())
+            .field("connected", &shared.is_connected())
+            .field("terminated", &shared.terminated)
+            .field("dispatched", &shared.dispatched)
+            .finish()
+    }
+}
+
+
+
+/// A handle being created by `UnsyncConnectable::connect` function.
+/// Original stream is not polled after every `UnsyncConnection` is dropped.
+pub struct UnsyncConnection<S: Stream> {
+    shared: Weak<SharedCell<Shared<S>, Handle>>,
+}
+
+
+
+impl<S: Stream> Drop for UnsyncConnection<S> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.upgrade() {
+            if let Some((mut shared, handle)) = shared.borrow_or_defer(Handle::Connection) {
+                shared.remove(handle);
+            }
+        }
+    }
+}
+
+
+
+impl<S: Stream> ::std::fmt::Debug for UnsyncConnection<S> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "UnsyncConnection(..)")
     }
 }
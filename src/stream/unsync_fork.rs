@@ -1,14 +1,17 @@
 use futures::{Stream, Poll, Async};
+use futures::task;
 
-use super::fork::Side;
+use super::fork::{Route, RouteError, Broadcast, ErrorStream};
+use super::fork_queues::{Queues, Routing};
+use super::DEFAULT_BUDGET;
+use util::SharedCell;
 
 use std::rc::Rc;
-use std::collections::VecDeque;
-use std::cell::RefCell;
 
 
-pub type LeftUnsyncFork<S, F> = UnsyncFork<S, F>;
-pub type RightUnsyncFork<S, F> = UnsyncFork<S, F>;
+pub type LeftUnsyncFork<S, F, G = Broadcast> = UnsyncFork<S, F, G>;
+pub type RightUnsyncFork<S, F, G = Broadcast> = UnsyncFork<S, F, G>;
+pub type UnsyncForkWithErrorStream<S, F> = UnsyncFork<S, F, ErrorStream>;
 
 
 
@@ -17,73 +20,171 @@ pub fn unsync_fork<S, F, T>(stream: S, router: F) -> (LeftUnsyncFork<S, F>, Righ
 where
     S: Stream,
     F: FnMut(&S::Item) -> T,
-    Side: From<T>,
+    T: Route<S::Item>,
 {
-    let shared = Shared {
-        router: router,
-        stream: stream,
-        queues: Queues::new(),
-    };
+    let mut forks = unsync_fork_n(stream, 2, router);
+    let right = forks.pop().unwrap();
+    let left = forks.pop().unwrap();
+    (left, right)
+}
 
-    let shared = Rc::new(RefCell::new(shared));
 
-    let left = UnsyncFork {
-        route: Side::Left,
-        shared: shared.clone(),
-    };
+/// UnsyncFork given stream into `n` streams. Please have a look at document of `StreamExt` trait.
+pub fn unsync_fork_n<S, F, T>(stream: S, n: usize, router: F) -> Vec<UnsyncFork<S, F>>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    new(stream, n, None, router, Broadcast, false)
+}
 
-    let right = UnsyncFork {
-        route: Side::Right,
-        shared: shared,
-    };
 
+/// UnsyncFork given stream into two streams with bounded queues. Please have a look at document of
+/// `StreamExt` trait.
+pub fn unsync_fork_bounded<S, F, T>(
+    stream: S,
+    capacity: usize,
+    router: F,
+) -> (LeftUnsyncFork<S, F>, RightUnsyncFork<S, F>)
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    let mut forks = unsync_fork_n_bounded(stream, 2, capacity, router);
+    let right = forks.pop().unwrap();
+    let left = forks.pop().unwrap();
     (left, right)
 }
 
 
+/// UnsyncFork given stream into `n` streams with bounded queues. Please have a look at document of
+/// `StreamExt` trait.
+pub fn unsync_fork_n_bounded<S, F, T>(
+    stream: S,
+    n: usize,
+    capacity: usize,
+    router: F,
+) -> Vec<UnsyncFork<S, F>>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    assert!(capacity > 0, "capacity of fork must be positive");
+    new(stream, n, Some(capacity), router, Broadcast, false)
+}
 
-#[derive(Debug)]
-struct Queues<T, E> {
-    left: VecDeque<Result<Option<T>, E>>,
-    right: VecDeque<Result<Option<T>, E>>,
+
+/// UnsyncFork given stream into two streams with an error policy. Please have a look at document
+/// of `StreamExt` trait.
+pub fn unsync_fork_with_errors<S, F, T, G>(
+    stream: S,
+    router: F,
+    errors: G,
+) -> (LeftUnsyncFork<S, F, G>, RightUnsyncFork<S, F, G>)
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+    G: RouteError<S::Error>,
+{
+    let mut forks = unsync_fork_n_with_errors(stream, 2, router, errors);
+    let right = forks.pop().unwrap();
+    let left = forks.pop().unwrap();
+    (left, right)
 }
 
 
-impl<T, E> Queues<T, E> {
-    fn new() -> Queues<T, E> {
-        Queues {
-            left: VecDeque::new(),
-            right: VecDeque::new(),
-        }
-    }
+/// UnsyncFork given stream into `n` streams with an error policy. Please have a look at document
+/// of `StreamExt` trait.
+pub fn unsync_fork_n_with_errors<S, F, T, G>(
+    stream: S,
+    n: usize,
+    router: F,
+    errors: G,
+) -> Vec<UnsyncFork<S, F, G>>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+    G: RouteError<S::Error>,
+{
+    new(stream, n, None, router, errors, false)
+}
 
-    fn get_queue_mut(&mut self, route: Side) -> &mut VecDeque<Result<Option<T>, E>> {
-        match route {
-            Side::Left => &mut self.left,
-            Side::Right => &mut self.right,
-        }
-    }
 
-    fn push_none(&mut self) {
-        self.left.push_back(Ok(None));
-        self.right.push_back(Ok(None));
-    }
+/// UnsyncFork given stream into `n` streams and a stream of its errors. Please have a look at
+/// document of `StreamExt` trait.
+pub fn unsync_fork_n_with_error_stream<S, F, T>(
+    stream: S,
+    n: usize,
+    router: F,
+) -> (Vec<UnsyncForkWithErrorStream<S, F>>, UnsyncForkErrors<S, F>)
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    let forks = new(stream, n, None, router, ErrorStream::new(), true);
 
-    fn push_err(&mut self, err: E)
-    where
-        E: Clone,
-    {
-        self.left.push_back(Err(err.clone()));
-        self.right.push_back(Err(err));
-    }
+    // The error stream reads the queue next to the last branch.
+    let errors = UnsyncFork {
+        index: n,
+        shared: forks[0].shared.clone(),
+        budget: DEFAULT_BUDGET,
+    };
+
+    (forks, UnsyncForkErrors { fork: errors })
+}
+
+
+fn new<S, F, T, G>(
+    stream: S,
+    n: usize,
+    capacity: Option<usize>,
+    router: F,
+    errors: G,
+    error_queue: bool,
+) -> Vec<UnsyncFork<S, F, G>>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    assert!(n > 0, "fork needs at least one branch");
+
+    let shared = Shared {
+        router: router,
+        errors: errors,
+        stream: stream,
+        queues: Queues::new(n, error_queue, capacity),
+        routing: Routing::new(n, error_queue),
+    };
+
+    let shared = Rc::new(SharedCell::new(shared));
+
+    (0..n)
+        .map(|index| {
+            UnsyncFork {
+                index: index,
+                shared: shared.clone(),
+                budget: DEFAULT_BUDGET,
+            }
+        })
+        .collect()
 }
 
 
+
 #[derive(Debug)]
-struct Shared<S: Stream, F> {
+struct Shared<S: Stream, F, G> {
     router: F,
+    errors: G,
     stream: S,
     queues: Queues<S::Item, S::Error>,
+    routing: Routing<S::Item, S::Error>,
 }
 
 
@@ -92,7 +193,9 @@ struct Shared<S: Stream, F> {
 /// The closure being passed this function is called "router". Each item of original stream is
 /// passed to branch following to "router" decision.
 /// "Router" can return not only `Side` which is `Left` or `Right` but also
-/// `bool` (`true` is considered as `Left`).
+/// `bool` (`true` is considered as `Left`). To send an item to both branches or to neither,
+/// return `Multicast`. It requires `Clone` items because `Both` sends a clone of the item to
+/// each branch.
 ///
 /// # Examples
 ///
@@ -108,62 +211,236 @@ struct Shared<S: Stream, F> {
 /// # }
 /// ```
 ///
+/// If you need more than two branches, use `unsync_fork_n` function. Its "router" returns the
+/// index of branch, `Branches` or any type implementing `Route` trait.
+///
+/// Queues of branches are unbounded unless they are created by `unsync_fork_bounded` or
+/// `unsync_fork_n_bounded`. Then original stream is not polled while an item waits for the full
+/// queue of its branch.
+///
+/// If a branch is dropped, items routed to it are discarded unless another branch calls
+/// `set_fallback`. Original stream is dropped with the last branch.
+///
+/// An error of original stream is sent to every branch unless another error policy is given by
+/// `unsync_fork_with_errors`, `unsync_fork_n_with_errors` or `unsync_fork_n_with_error_stream`.
+///
 /// # Notice
 ///
 /// The value being returned by this function is not `Sync`. We will provide `Sync` version later.
-pub struct UnsyncFork<S: Stream, F> {
-    route: Side,
-    shared: Rc<RefCell<Shared<S, F>>>,
+pub struct UnsyncFork<S: Stream, F, G = Broadcast> {
+    index: usize,
+    shared: Rc<SharedCell<Shared<S, F, G>, usize>>,
+    // Maximum number of times one poll of this branch polls original stream.
+    budget: usize,
 }
 
 
-impl<S, F, T> Stream for UnsyncFork<S, F>
+impl<S, F, T, G> Stream for UnsyncFork<S, F, G>
 where
     S: Stream,
-    S::Error: Clone,
     F: FnMut(&S::Item) -> T,
-    T: Into<Side>,
+    T: Route<S::Item>,
+    G: RouteError<S::Error>,
 {
     type Item = S::Item;
     type Error = S::Error;
 
     fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
-        {
-            let mut shared = self.shared.borrow_mut();
+        let mut shared = self.shared.borrow_mut();
+        let shared = &mut *shared;
+        let mut polled = 0;
 
-            let msg = shared.queues.get_queue_mut(self.route.clone()).pop_front();
+        loop {
+            close_dropped(shared, &self.shared);
+
+            let msg = shared.queues.pop(self.index);
 
             let poll = match msg {
                 Some(Ok(Some(msg))) => return Ok(Async::Ready(Some(msg))),
                 Some(Ok(None)) => return Ok(Async::Ready(None)),
                 Some(Err(e)) => return Err(e),
+                None if shared.queues.is_paused() => {
+                    // Some branch must read its full queue before original stream is polled.
+                    shared.queues.block();
+                    return Ok(Async::NotReady);
+                }
+                None if polled == self.budget => {
+                    // Original stream keeps producing items for other branches.
+                    // Yield to other tasks, and poll it again soon.
+                    task::current().notify();
+                    return Ok(Async::NotReady);
+                }
                 None => shared.stream.poll(),
             };
+            polled += 1;
+            // Original stream may drop branches. Their items must not be pushed.
+            close_dropped(shared, &self.shared);
+
+            // We are going to read our queue right now.
+            shared.queues.unpark(self.index);
 
             match poll {
-                Err(e) => shared.queues.push_err(e),
+                Err(e) => {
+                    shared.routing.route_error(&mut shared.errors, e);
+                    shared.queues.push_err(&mut shared.routing);
+                }
                 Ok(Async::Ready(Some(msg))) => {
-                    let route = (&mut shared.router)(&msg).into();
-                    shared.queues.get_queue_mut(route).push_back(Ok(Some(msg)));
+                    let route = (&mut shared.router)(&msg);
+                    shared.routing.route_item(route, msg);
+                    shared.queues.push_item(&mut shared.routing);
                 }
                 Ok(Async::Ready(None)) => shared.queues.push_none(),
                 Ok(Async::NotReady) => {
+                    // Original stream may notify only the branch polling it last. So other
+                    // branches are notified when an item is pushed to their queues.
+                    shared.queues.park(self.index);
                     return Ok(Async::NotReady);
                 }
             }
         }
+    }
+}
 
-        self.poll()
+
+
+impl<S: Stream, F, G> UnsyncFork<S, F, G> {
+    /// Returns the index of this branch. `Left` is 0 and `Right` is 1.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the number of items which are routed to this branch but not read yet.
+    pub fn queue_len(&self) -> usize {
+        self.shared.borrow().queues.get_queue(self.index).len()
+    }
+
+    /// Returns `true` if original stream has finished.
+    pub fn is_terminated(&self) -> bool {
+        self.shared.borrow().queues.is_terminated()
+    }
+
+    /// Makes this branch receive items which are routed to dropped branches. Without a fallback
+    /// branch, those items are discarded.
+    pub fn set_fallback(&self) {
+        self.shared.borrow_mut().queues.set_fallback(self.index);
+    }
+
+    /// Returns the number of items which original stream has produced.
+    pub fn dispatched(&self) -> u64 {
+        self.shared.borrow().queues.dispatched()
+    }
+
+    /// Sets the maximum number of times one poll of this branch polls original stream without
+    /// getting an item. After that, the poll yields to other tasks by notifying current task and
+    /// returning `NotReady`. The default is 32.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `budget` is 0.
+    pub fn set_budget(&mut self, budget: usize) {
+        assert!(budget > 0, "budget must be positive");
+        self.budget = budget;
     }
 }
 
 
 
-impl<S: Stream, F> ::std::fmt::Debug for UnsyncFork<S, F> {
+impl<S: Stream, F, G> Drop for UnsyncFork<S, F, G> {
+    fn drop(&mut self) {
+        // Original stream may drop a branch while it is polled. Then the poller closes its queue.
+        if let Some((mut shared, index)) = self.shared.borrow_or_defer(self.index) {
+            shared.queues.close(index);
+
+            // Items of this queue may hold branches.
+            close_dropped(&mut shared, &self.shared);
+        }
+    }
+}
+
+
+/// Closes queues of branches which are dropped while `Shared` is borrowed.
+fn close_dropped<S: Stream, F, G>(
+    shared: &mut Shared<S, F, G>,
+    cell: &SharedCell<Shared<S, F, G>, usize>,
+) {
+    loop {
+        let dropped = cell.take_dropped();
+        if dropped.is_empty() {
+            return;
+        }
+
+        for index in dropped {
+            shared.queues.close(index);
+        }
+    }
+}
+
+
+
+impl<S: Stream, F, G> ::std::fmt::Debug for UnsyncFork<S, F, G> {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-        match self.route {
-            Side::Left => write!(f, "LeftRoute"),
-            Side::Right => write!(f, "RightRoute"),
+        f.debug_struct("UnsyncFork")
+            .field("index", &self.index)
+            .field("queue_len", &self.queue_len())
+            .field("terminated", &self.is_terminated())
+            .field("dispatched", &self.dispatched())
+            .finish()
+    }
+}
+
+
+
+/// A stream of errors of original stream being created by `unsync_fork_n_with_error_stream`.
+///
+/// Each error of original stream comes here as an item instead of going to branches. This
+/// finishes when original stream finishes.
+///
+/// # Notice
+///
+/// The value being returned by this function is not `Sync`.
+pub struct UnsyncForkErrors<S: Stream, F> {
+    fork: UnsyncFork<S, F, ErrorStream>,
+}
+
+
+impl<S, F, T> Stream for UnsyncForkErrors<S, F>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> T,
+    T: Route<S::Item>,
+{
+    type Item = S::Error;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<S::Error>, ()> {
+        match self.fork.poll() {
+            Ok(Async::Ready(Some(_))) => unreachable!("an item is routed to the error stream"),
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => Ok(Async::Ready(Some(e))),
         }
     }
 }
+
+
+impl<S: Stream, F> UnsyncForkErrors<S, F> {
+    /// Sets the maximum number of times one poll of this stream polls original stream without
+    /// getting an error. Please have a look at `set_budget` of branches.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `budget` is 0.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.fork.set_budget(budget);
+    }
+}
+
+
+impl<S: Stream, F> ::std::fmt::Debug for UnsyncForkErrors<S, F> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        f.debug_struct("UnsyncForkErrors")
+            .field("queue_len", &self.fork.queue_len())
+            .field("terminated", &self.fork.is_terminated())
+            .finish()
+    }
+}
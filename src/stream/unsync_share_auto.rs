@@ -0,0 +1,103 @@
+use futures::Stream;
+
+use super::unsync_cloneable::{unsync_cloneable, UnsyncCloneable, WeakUnsyncCloneable};
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+
+/// Creates `UnsyncShareAuto` which creates original stream by `factory` when it is needed.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate ex_futures;
+/// use ex_futures::stream::unsync_share_auto;
+/// use futures::{Future, Stream};
+///
+/// # fn main() {
+/// // Opens an expensive feed only while somebody subscribes it.
+/// let feed = unsync_share_auto(|| ::futures::stream::iter_ok::<_, ()>(0..3));
+///
+/// let rx = feed.subscribe(); // Creates original stream.
+/// assert_eq!(rx.collect().wait(), Ok(vec![0, 1, 2])); // Drops original stream at last.
+///
+/// let rx = feed.subscribe(); // Creates original stream again.
+/// assert_eq!(rx.collect().wait(), Ok(vec![0, 1, 2]));
+/// # }
+/// ```
+pub fn unsync_share_auto<S, F>(factory: F) -> UnsyncShareAuto<S, F>
+where
+    S: Stream,
+    F: FnMut() -> S,
+{
+    let inner = Inner {
+        factory: factory,
+        current: None,
+    };
+
+    UnsyncShareAuto { inner: Rc::new(RefCell::new(inner)) }
+}
+
+
+struct Inner<S: Stream, F> {
+    factory: F,
+    // Weak handle of original stream which is currently shared.
+    current: Option<WeakUnsyncCloneable<S>>,
+}
+
+
+/// A source of `UnsyncCloneable` streams being created by `unsync_share_auto` function.
+///
+/// Original stream is created by the factory when the first subscriber appears, and is dropped
+/// when every subscriber is dropped. The next subscriber creates it again. It is also created
+/// again when it has finished, even if old subscribers are still living.
+///
+/// `UnsyncShareAuto` is cheap to clone and every clone shares the same original stream.
+pub struct UnsyncShareAuto<S: Stream, F> {
+    inner: Rc<RefCell<Inner<S, F>>>,
+}
+
+
+
+impl<S, F> UnsyncShareAuto<S, F>
+where
+    S: Stream,
+    S::Item: Clone,
+    S::Error: Clone,
+    F: FnMut() -> S,
+{
+    /// Creates a new subscriber which reads items arriving after now.
+    /// This creates original stream if no subscriber is living or it has finished.
+    pub fn subscribe(&self) -> UnsyncCloneable<S> {
+        let mut inner = self.inner.borrow_mut();
+
+        if let Some(rx) = inner.current.as_ref().and_then(WeakUnsyncCloneable::upgrade) {
+            if !rx.is_terminated() {
+                return rx;
+            }
+            // Old subscribers are still living, but original stream has already finished.
+        }
+
+        let rx = unsync_cloneable((inner.factory)());
+        inner.current = Some(rx.downgrade());
+        rx
+    }
+}
+
+
+
+impl<S: Stream, F> Clone for UnsyncShareAuto<S, F> {
+    fn clone(&self) -> Self {
+        UnsyncShareAuto { inner: self.inner.clone() }
+    }
+}
+
+
+
+impl<S: Stream, F> ::std::fmt::Debug for UnsyncShareAuto<S, F> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "UnsyncShareAuto(..)")
+    }
+}
@@ -0,0 +1,245 @@
+use futures::{Future, Stream, Sink, Poll, Async, AsyncSink, StartSend};
+use futures::sync::mpsc;
+
+use super::unbounded::{unbounded, UnboundedSender, UnboundedReceiver, SendError};
+
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+
+
+/// A hub which connects publish-subscribe channels living on different threads.
+///
+/// `Bridge` is `Send` and `Clone`, so you can hand it to each thread. Each thread calls
+/// `Bridge::join` and gets its own single-threaded channel. Every item sent through a
+/// `BridgeSender` is published into the local channel and also forwarded to every other thread
+/// which has joined the bridge. Items arriving from other threads are published only into the
+/// local channel, so they never echo back.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate ex_futures;
+/// use ex_futures::unsync::pubsub::Bridge;
+///
+/// # fn main() {
+/// let bridge = Bridge::<usize>::new();
+/// let bridge2 = bridge.clone(); // Send this to another thread.
+///
+/// let (tx, rx, import) = bridge.join();
+/// // Spawn `import` on the event loop of this thread.
+/// # }
+/// ```
+pub struct Bridge<T> {
+    peers: Arc<Mutex<Peers<T>>>,
+}
+
+
+type PeerId = usize;
+
+struct Peers<T> {
+    next_id: PeerId,
+    senders: Vec<(PeerId, mpsc::UnboundedSender<T>)>,
+}
+
+
+/// `Peers` is always consistent because a peer is added or removed at once. So a panic while
+/// cloning an item does not break it.
+fn lock_peers<T>(peers: &Mutex<Peers<T>>) -> MutexGuard<'_, Peers<T>> {
+    peers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+
+impl<T: Clone + Send> Bridge<T> {
+    pub fn new() -> Bridge<T> {
+        let peers = Peers {
+            next_id: 0,
+            senders: Vec::new(),
+        };
+        Bridge { peers: Arc::new(Mutex::new(peers)) }
+    }
+
+    /// Joins this thread to the bridge.
+    ///
+    /// Returns a sender, a receiver of the local channel and an `Import` future.
+    /// `Import` publishes items coming from other threads into the local channel, so you need to
+    /// spawn it on the event loop of current thread.
+    pub fn join(&self) -> (BridgeSender<T>, UnboundedReceiver<T>, Import<T>) {
+        let (local_tx, local_rx) = unbounded();
+        let (remote_tx, remote_rx) = mpsc::unbounded();
+
+        let id = {
+            let mut peers = lock_peers(&self.peers);
+            let id = peers.next_id;
+            peers.next_id = id.wrapping_add(1);
+            peers.senders.push((id, remote_tx));
+            id
+        };
+
+        let local_tx = Rc::new(local_tx);
+
+        let sender = BridgeSender {
+            id: id,
+            local: local_tx.clone(),
+            peers: self.peers.clone(),
+        };
+
+        let import = Import {
+            id: id,
+            remote: remote_rx,
+            local: local_tx,
+            peers: self.peers.clone(),
+        };
+
+        (sender, local_rx, import)
+    }
+
+    /// Returns the number of threads whose `Import` is living.
+    pub fn peer_count(&self) -> usize {
+        lock_peers(&self.peers).senders.len()
+    }
+}
+
+
+impl<T: Clone + Send> Default for Bridge<T> {
+    fn default() -> Bridge<T> {
+        Bridge::new()
+    }
+}
+
+
+impl<T> Clone for Bridge<T> {
+    fn clone(&self) -> Self {
+        Bridge { peers: self.peers.clone() }
+    }
+}
+
+
+impl<T> ::std::fmt::Debug for Bridge<T> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "Bridge(..)")
+    }
+}
+
+
+
+/// The transmission end of a bridged channel.
+/// This is created by the `Bridge::join` function.
+pub struct BridgeSender<T> {
+    id: PeerId,
+    local: Rc<UnboundedSender<T>>,
+    peers: Arc<Mutex<Peers<T>>>,
+}
+
+
+impl<T: Clone + Send> BridgeSender<T> {
+    /// Publishes an item into the local channel and forwards it to other threads.
+    /// This fails only when there is no receiver at all.
+    pub fn unbounded_send(&self, msg: T) -> Result<(), SendError<T>> {
+        let forwarded = self.forward(&msg);
+        match self.local.unbounded_send(msg) {
+            Ok(()) => Ok(()),
+            Err(_) if forwarded => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn forward(&self, msg: &T) -> bool {
+        let mut peers = lock_peers(&self.peers);
+        let id = self.id;
+        let mut forwarded = false;
+
+        // Remove peers whose thread is already gone.
+        peers.senders.retain(|&(peer_id, ref tx)| {
+            if peer_id == id {
+                return true;
+            }
+            match tx.unbounded_send(msg.clone()) {
+                Ok(()) => {
+                    forwarded = true;
+                    true
+                }
+                Err(_) => false,
+            }
+        });
+
+        forwarded
+    }
+}
+
+
+impl<T: Clone + Send> Sink for BridgeSender<T> {
+    type SinkItem = T;
+    type SinkError = SendError<T>;
+
+    fn start_send(&mut self, msg: T) -> StartSend<T, SendError<T>> {
+        self.unbounded_send(msg).map(|()| AsyncSink::Ready)
+    }
+
+
+    fn poll_complete(&mut self) -> Poll<(), SendError<T>> {
+        Ok(Async::Ready(()))
+    }
+
+
+    fn close(&mut self) -> Poll<(), SendError<T>> {
+        Ok(Async::Ready(()))
+    }
+}
+
+
+impl<T> ::std::fmt::Debug for BridgeSender<T> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "BridgeSender(..)")
+    }
+}
+
+
+
+/// A future which publishes items coming from other threads into the local channel.
+/// This is created by the `Bridge::join` function.
+///
+/// This future completes when the bridge is gone or when every local receiver is dropped.
+pub struct Import<T> {
+    id: PeerId,
+    remote: mpsc::UnboundedReceiver<T>,
+    local: Rc<UnboundedSender<T>>,
+    peers: Arc<Mutex<Peers<T>>>,
+}
+
+
+impl<T> Future for Import<T> {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            match try_ready!(self.remote.poll()) {
+                Some(msg) => {
+                    if self.local.unbounded_send(msg).is_err() {
+                        // No local receiver is available.
+                        return Ok(Async::Ready(()));
+                    }
+                }
+                None => return Ok(Async::Ready(())),
+            }
+        }
+    }
+}
+
+
+impl<T> Drop for Import<T> {
+    fn drop(&mut self) {
+        // Other threads need not to forward items to this thread any more.
+        let id = self.id;
+        lock_peers(&self.peers).senders.retain(|&(peer_id, _)| peer_id != id);
+    }
+}
+
+
+impl<T> ::std::fmt::Debug for Import<T> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "Import(..)")
+    }
+}
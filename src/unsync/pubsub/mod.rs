@@ -1,4 +1,8 @@
 //! Future-aware single-threaded publish-subscribe channel
 mod unbounded;
+mod bridge;
 
 pub use self::unbounded::{unbounded, UnboundedSender, UnboundedReceiver, SendError};
+pub use self::bridge::{Bridge, BridgeSender, Import};
+#[cfg(feature = "net")]
+pub mod net;
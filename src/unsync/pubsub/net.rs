@@ -0,0 +1,371 @@
+//! Publish-subscribe channel over a local socket.
+//!
+//! `serve` exposes a channel to other processes. Each connection accepted by the server
+//! subscribes to the channel and receives every item published after it is accepted.
+//! `subscribe` presents the remote channel as a local `Stream`.
+//!
+//! Both sides are generic over any `AsyncRead`/`AsyncWrite` connection, so you can use a loopback
+//! TCP socket or a Unix domain socket. Items are encoded by a user-supplied `Codec` and framed
+//! with a 4 bytes big-endian length prefix. A frame longer than 8 MiB is rejected by default; use
+//! `set_max_frame_len` to change the limit.
+use futures::{Future, Stream, Sink, Poll, Async, AsyncSink};
+use bytes::{BytesMut, BufMut};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_codec::{Encoder, Decoder, FramedRead, FramedWrite};
+
+use super::unbounded::UnboundedReceiver;
+
+use std::rc::Rc;
+use std::io;
+
+
+
+/// Converts items into bytes and bytes into items.
+pub trait Codec {
+    type Item;
+
+    /// Encodes an item and appends it to `buf`.
+    fn encode(&mut self, item: &Self::Item, buf: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Decodes an item from exactly one frame.
+    fn decode(&mut self, buf: &[u8]) -> io::Result<Self::Item>;
+}
+
+
+const HEADER_LEN: usize = 4;
+
+const DEFAULT_MAX_FRAME_LEN: usize = 8 * 1024 * 1024;
+
+struct Frames<C> {
+    codec: C,
+    max_len: usize,
+}
+
+
+impl<C> Frames<C> {
+    fn new(codec: C, max_len: usize) -> Frames<C> {
+        Frames {
+            codec: codec,
+            max_len: max_len,
+        }
+    }
+}
+
+
+impl<C: Codec> Encoder for Frames<C> {
+    type Item = Rc<C::Item>;
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Rc<C::Item>, dst: &mut BytesMut) -> io::Result<()> {
+        let mut buf = Vec::new();
+        self.codec.encode(&item, &mut buf)?;
+
+        if buf.len() > self.max_len || buf.len() > u32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame is too large"));
+        }
+
+        dst.reserve(HEADER_LEN + buf.len());
+        dst.put_u32_be(buf.len() as u32);
+        dst.put_slice(&buf);
+        Ok(())
+    }
+}
+
+
+impl<C: Codec> Decoder for Frames<C> {
+    type Item = C::Item;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<C::Item>> {
+        if src.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let len = src[..HEADER_LEN].iter().fold(0, |len, b| (len << 8) | *b as usize);
+        if len > self.max_len {
+            // Do not let the peer make us allocate a huge buffer.
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frame is too large"));
+        }
+        if src.len() < HEADER_LEN + len {
+            src.reserve(HEADER_LEN + len - src.len());
+            return Ok(None);
+        }
+
+        let frame = src.split_to(HEADER_LEN + len);
+        self.codec.decode(&frame[HEADER_LEN..]).map(Some)
+    }
+}
+
+
+
+/// Serves given channel to every connection coming from `incoming`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate ex_futures;
+/// # extern crate tokio_core;
+/// use ex_futures::unsync::pubsub::unbounded;
+/// use ex_futures::unsync::pubsub::net::{serve, Codec};
+/// use futures::{Future, Stream};
+/// use tokio_core::reactor::Core;
+/// use tokio_core::net::TcpListener;
+/// use std::io;
+///
+/// #[derive(Clone)]
+/// struct Utf8;
+///
+/// impl Codec for Utf8 {
+///     type Item = String;
+///
+///     fn encode(&mut self, item: &String, buf: &mut Vec<u8>) -> io::Result<()> {
+///         buf.extend_from_slice(item.as_bytes());
+///         Ok(())
+///     }
+///
+///     fn decode(&mut self, buf: &[u8]) -> io::Result<String> {
+///         String::from_utf8(buf.to_vec())
+///             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+///     }
+/// }
+///
+/// # fn main() {
+/// let core = Core::new().unwrap();
+/// let addr = "127.0.0.1:0".parse().unwrap();
+/// let listener = TcpListener::bind(&addr, &core.handle()).unwrap();
+///
+/// let (tx, rx) = unbounded::<String>();
+/// let server = serve(listener.incoming().map(|(sock, _)| sock), rx, Utf8);
+/// core.handle().spawn(server.map_err(|_| ()));
+/// # }
+/// ```
+pub fn serve<I, C>(incoming: I, rx: UnboundedReceiver<C::Item>, codec: C) -> Server<I, C>
+where
+    I: Stream,
+    I::Item: AsyncWrite,
+    C: Codec + Clone,
+{
+    Server {
+        incoming: Some(incoming),
+        rx: Some(rx),
+        connections: Vec::new(),
+        codec: codec,
+        max_frame_len: DEFAULT_MAX_FRAME_LEN,
+    }
+}
+
+
+/// A future which serves a channel to every accepted connection.
+/// This is created by the `serve` function.
+///
+/// This future completes when every connection is finished after either `incoming` is exhausted
+/// or the channel is closed. It fails only when `incoming` fails.
+pub struct Server<I, C>
+where
+    I: Stream,
+    C: Codec,
+{
+    incoming: Option<I>,
+    rx: Option<UnboundedReceiver<C::Item>>,
+    connections: Vec<Connection<I::Item, C>>,
+    codec: C,
+    max_frame_len: usize,
+}
+
+
+impl<I, C> Future for Server<I, C>
+where
+    I: Stream,
+    I::Item: AsyncWrite,
+    C: Codec + Clone,
+{
+    type Item = ();
+    type Error = I::Error;
+
+    fn poll(&mut self) -> Poll<(), I::Error> {
+        self.accept()?;
+        self.drain();
+
+        for conn in self.connections.iter_mut() {
+            conn.poll();
+        }
+        // Remove finished or broken connections.
+        self.connections.retain(|conn| conn.is_alive());
+
+        if self.connections.is_empty() && (self.incoming.is_none() || self.rx.is_none()) {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+
+impl<I, C> Server<I, C>
+where
+    I: Stream,
+    I::Item: AsyncWrite,
+    C: Codec + Clone,
+{
+    /// Sets the maximum length of a frame in bytes. A connection is closed when an item is
+    /// encoded into a longer frame. This applies to connections accepted after this call. The
+    /// default is 8 MiB.
+    pub fn set_max_frame_len(&mut self, len: usize) {
+        self.max_frame_len = len;
+    }
+
+    fn accept(&mut self) -> Result<(), I::Error> {
+        loop {
+            let io = match self.incoming.as_mut().map(Stream::poll) {
+                None => return Ok(()),
+                Some(Err(e)) => return Err(e),
+                Some(Ok(Async::NotReady)) => return Ok(()),
+                Some(Ok(Async::Ready(None))) => {
+                    self.incoming = None;
+                    return Ok(());
+                }
+                Some(Ok(Async::Ready(Some(io)))) => io,
+            };
+
+            if let Some(ref rx) = self.rx {
+                // New subscriber sees only items published after now.
+                let frames = Frames::new(self.codec.clone(), self.max_frame_len);
+                let conn = Connection {
+                    rx: rx.clone(),
+                    frames: FramedWrite::new(io, frames),
+                    buffered: None,
+                    state: ConnectionState::Alive,
+                };
+                self.connections.push(conn);
+            }
+        }
+    }
+
+    /// Discards items queued for `self.rx`. That receiver is used only to create subscribers.
+    fn drain(&mut self) {
+        while let Some(poll) = self.rx.as_mut().map(Stream::poll) {
+            match poll {
+                Ok(Async::Ready(Some(_))) => (),
+                Ok(Async::Ready(None)) | Err(()) => self.rx = None,
+                Ok(Async::NotReady) => return,
+            }
+        }
+    }
+}
+
+
+impl<I, C> ::std::fmt::Debug for Server<I, C>
+where
+    I: Stream,
+    C: Codec,
+{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "Server(connections: {})", self.connections.len())
+    }
+}
+
+
+
+#[derive(PartialEq)]
+enum ConnectionState {
+    Alive,
+    Finished,
+}
+
+
+struct Connection<IO, C: Codec> {
+    rx: UnboundedReceiver<C::Item>,
+    frames: FramedWrite<IO, Frames<C>>,
+    buffered: Option<Rc<C::Item>>,
+    state: ConnectionState,
+}
+
+
+impl<IO, C> Connection<IO, C>
+where
+    IO: AsyncWrite,
+    C: Codec,
+{
+    fn is_alive(&self) -> bool {
+        self.state == ConnectionState::Alive
+    }
+
+    fn poll(&mut self) {
+        // Any io error just closes the connection.
+        match self.poll_send() {
+            Ok(Async::NotReady) => (),
+            Ok(Async::Ready(())) | Err(_) => self.state = ConnectionState::Finished,
+        }
+    }
+
+    fn poll_send(&mut self) -> Poll<(), io::Error> {
+        loop {
+            if let Some(msg) = self.buffered.take() {
+                if let AsyncSink::NotReady(msg) = self.frames.start_send(msg)? {
+                    self.buffered = Some(msg);
+                    try_ready!(self.frames.poll_complete());
+                    continue;
+                }
+            }
+
+            match self.rx.poll() {
+                Ok(Async::Ready(Some(msg))) => self.buffered = Some(msg),
+                Ok(Async::Ready(None)) | Err(()) => return self.frames.close(),
+                Ok(Async::NotReady) => {
+                    try_ready!(self.frames.poll_complete());
+                    return Ok(Async::NotReady);
+                }
+            }
+        }
+    }
+}
+
+
+
+/// Subscribes a channel served by `serve` function through given connection.
+pub fn subscribe<IO, C>(io: IO, codec: C) -> Subscriber<IO, C>
+where
+    IO: AsyncRead,
+    C: Codec,
+{
+    Subscriber { frames: FramedRead::new(io, Frames::new(codec, DEFAULT_MAX_FRAME_LEN)) }
+}
+
+
+/// A stream of items published on a remote channel.
+/// This is created by the `subscribe` function.
+pub struct Subscriber<IO, C> {
+    frames: FramedRead<IO, Frames<C>>,
+}
+
+
+impl<IO, C> Stream for Subscriber<IO, C>
+where
+    IO: AsyncRead,
+    C: Codec,
+{
+    type Item = C::Item;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<C::Item>, io::Error> {
+        self.frames.poll()
+    }
+}
+
+
+impl<IO, C> Subscriber<IO, C> {
+    /// Sets the maximum length of a frame in bytes. When the remote channel sends a longer frame,
+    /// this stream fails with an error of `io::ErrorKind::InvalidData` without reading the frame.
+    /// The default is 8 MiB.
+    pub fn set_max_frame_len(&mut self, len: usize) {
+        self.frames.decoder_mut().max_len = len;
+    }
+}
+
+
+impl<IO, C> ::std::fmt::Debug for Subscriber<IO, C> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "Subscriber(..)")
+    }
+}
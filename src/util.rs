@@ -1,3 +1,6 @@
+use std::cell::{RefCell, Ref, RefMut};
+
+
 pub struct Should<T>(Option<T>);
 
 impl<T> Should<T> {
@@ -23,3 +26,163 @@ impl<T> Should<T> {
         )
     }
 }
+
+
+/// A storage which reuses vacant slots and gives each value a stable index.
+pub(crate) struct Slab<T> {
+    entries: Vec<Entry<T>>,
+    // Head of the list of vacant entries.
+    next_vacant: usize,
+    len: usize,
+}
+
+
+enum Entry<T> {
+    Occupied(T),
+    // Index of the next vacant entry.
+    Vacant(usize),
+}
+
+
+impl<T> Slab<T> {
+    pub(crate) fn new() -> Slab<T> {
+        Slab {
+            entries: Vec::new(),
+            next_vacant: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Stores a value and returns its index.
+    pub(crate) fn insert(&mut self, value: T) -> usize {
+        let key = self.next_vacant;
+
+        if key == self.entries.len() {
+            self.entries.push(Entry::Occupied(value));
+            self.next_vacant = key + 1;
+        } else {
+            match ::std::mem::replace(&mut self.entries[key], Entry::Occupied(value)) {
+                Entry::Vacant(next) => self.next_vacant = next,
+                Entry::Occupied(_) => unreachable!("vacant list points an occupied entry"),
+            }
+        }
+
+        self.len += 1;
+        key
+    }
+
+    /// Removes a value. Its index may be reused later.
+    pub(crate) fn remove(&mut self, key: usize) -> Option<T> {
+        match self.entries.get(key) {
+            Some(Entry::Occupied(_)) => (),
+            _ => return None,
+        }
+
+        let entry = ::std::mem::replace(&mut self.entries[key], Entry::Vacant(self.next_vacant));
+        self.next_vacant = key;
+        self.len -= 1;
+
+        match entry {
+            Entry::Occupied(value) => Some(value),
+            Entry::Vacant(_) => None,
+        }
+    }
+
+    pub(crate) fn get(&self, key: usize) -> Option<&T> {
+        match self.entries.get(key) {
+            Some(Entry::Occupied(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, key: usize) -> Option<&mut T> {
+        match self.entries.get_mut(key) {
+            Some(Entry::Occupied(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter().filter_map(|entry| match entry {
+            Entry::Occupied(value) => Some(value),
+            Entry::Vacant(_) => None,
+        })
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.entries.iter_mut().filter_map(|entry| match entry {
+            Entry::Occupied(value) => Some(value),
+            Entry::Vacant(_) => None,
+        })
+    }
+}
+
+
+impl<T> Default for Slab<T> {
+    fn default() -> Slab<T> {
+        Slab::new()
+    }
+}
+
+
+impl<T> ::std::fmt::Debug for Slab<T> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "Slab(len: {})", self.len)
+    }
+}
+
+
+
+/// A `RefCell` shared by handles of an unsync stream.
+///
+/// A handle may be dropped while the value is borrowed, for example by original stream being
+/// polled. Then the handle is recorded instead, and the holder of the value removes it by
+/// `take_dropped`.
+pub(crate) struct SharedCell<T, D> {
+    value: RefCell<T>,
+    // Handles dropped while `value` is borrowed.
+    dropped: RefCell<Vec<D>>,
+}
+
+
+impl<T, D> SharedCell<T, D> {
+    pub(crate) fn new(value: T) -> SharedCell<T, D> {
+        SharedCell {
+            value: RefCell::new(value),
+            dropped: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn borrow(&self) -> Ref<'_, T> {
+        self.value.borrow()
+    }
+
+    pub(crate) fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.value.borrow_mut()
+    }
+
+    /// Borrows the value to remove a dropped handle, and gives `handle` back. If it is already
+    /// borrowed, this records `handle` and returns `None`.
+    pub(crate) fn borrow_or_defer(&self, handle: D) -> Option<(RefMut<'_, T>, D)> {
+        match self.value.try_borrow_mut() {
+            Ok(value) => Some((value, handle)),
+            Err(_) => {
+                self.dropped.borrow_mut().push(handle);
+                None
+            }
+        }
+    }
+
+    /// Takes handles which are dropped while the value is borrowed.
+    pub(crate) fn take_dropped(&self) -> Vec<D> {
+        ::std::mem::take(&mut *self.dropped.borrow_mut())
+    }
+}
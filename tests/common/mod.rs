@@ -0,0 +1,58 @@
+//! Fixtures shared by stream tests.
+
+// Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use futures::{Stream, Poll};
+use futures::executor::Notify;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::any::Any;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+/// A value which `DropOnPoll` drops.
+pub type Victim = Rc<RefCell<Option<Box<dyn Any>>>>;
+
+
+/// Drops `victim` when original stream is polled.
+pub struct DropOnPoll<S> {
+    stream: S,
+    victim: Victim,
+}
+
+impl<S: Stream> Stream for DropOnPoll<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        let victim = self.victim.borrow_mut().take();
+        drop(victim);
+        self.stream.poll()
+    }
+}
+
+pub fn drop_on_poll<S>(stream: S) -> (DropOnPoll<S>, Victim) {
+    let victim = Rc::new(RefCell::new(None));
+    let stream = DropOnPoll {
+        stream: stream,
+        victim: victim.clone(),
+    };
+    (stream, victim)
+}
+
+
+/// Remembers whether a task is notified.
+pub struct Flag(pub AtomicBool);
+
+impl Notify for Flag {
+    fn notify(&self, _id: usize) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+pub fn flag() -> Arc<Flag> {
+    Arc::new(Flag(AtomicBool::new(false)))
+}
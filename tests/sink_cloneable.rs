@@ -5,7 +5,7 @@ extern crate tokio_core;
 use futures::{Future, Stream, Sink};
 use futures::sync::mpsc::{channel, unbounded, SendError};
 
-use ex_futures::SinkExt;
+use ex_futures::{SinkExt, SharedError};
 
 
 
@@ -43,6 +43,33 @@ fn join() {
 
 
 
+#[test]
+fn poisoned() {
+    let (tx, rx) = unbounded::<usize>();
+    let tx = tx.with(|i| {
+        assert!(i != 1, "boom");
+        Ok::<_, SendError<usize>>(i)
+    });
+
+    let cloneable = tx.cloneable();
+    let cloneable2 = cloneable.clone();
+
+    let res = std::thread::spawn(move || {
+        let cloneable = cloneable.send(0).wait().unwrap();
+        cloneable.send(1).wait() // Panics while sending.
+    }).join();
+    assert!(res.is_err());
+
+    match cloneable2.send(2).wait() {
+        Err(SharedError::Poisoned) => (),
+        _ => panic!("Sink should be poisoned"),
+    }
+
+    assert_eq!(rx.collect().wait().unwrap(), [0]);
+}
+
+
+
 fn new_stream() -> Box<Stream<Item = usize, Error = SendError<usize>>> {
     let (tx, rx) = channel(1);
 
@@ -2,14 +2,22 @@ extern crate ex_futures;
 extern crate futures;
 extern crate tokio_core;
 
-use futures::{Future, Stream};
-use futures::stream::unfold;
-use futures::future::ok;
+use futures::{Future, Stream, Async};
+use futures::stream::{unfold, iter_ok, iter_result};
+use futures::future::{ok, lazy};
 
-use ex_futures::StreamExt;
+use ex_futures::{StreamExt, SharedError};
+use ex_futures::stream::Eviction;
 
 use tokio_core::reactor::Core;
 
+use std::sync::{Arc, Mutex};
+use std::any::Any;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
 
 
 #[test]
@@ -76,3 +84,701 @@ fn zip() {
     assert_eq!(res1, [0, 1, 2, 3]);
     assert_eq!(res2, [0, 1, 2, 3]);
 }
+
+
+#[test]
+fn bounded() {
+    let pulled = Arc::new(AtomicUsize::new(0));
+    let pulled2 = pulled.clone();
+    let stream = iter_ok::<_, u8>(0..8).inspect(move |_| {
+        pulled2.fetch_add(1, Ordering::SeqCst);
+    });
+
+    let mut cloneable = stream.cloneable_bounded(2);
+    let mut cloneable2 = cloneable.clone();
+
+    lazy(|| {
+        assert_eq!(cloneable.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(cloneable.poll(), Ok(Async::Ready(Some(1))));
+        // Queue of "cloneable2" is full.
+        assert_eq!(cloneable.poll(), Ok(Async::NotReady));
+        assert_eq!(pulled.load(Ordering::SeqCst), 2);
+
+        assert_eq!(cloneable2.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(cloneable.poll(), Ok(Async::Ready(Some(2))));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+
+    let (res1, res2) = cloneable.collect().join(cloneable2.collect()).wait().unwrap();
+    assert_eq!(res1, [3, 4, 5, 6, 7]);
+    assert_eq!(res2, [1, 2, 3, 4, 5, 6, 7]);
+}
+
+
+#[derive(Debug)]
+struct Counted(Arc<AtomicUsize>);
+
+impl Clone for Counted {
+    fn clone(&self) -> Counted {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Counted(self.0.clone())
+    }
+}
+
+
+#[test]
+fn store_item_once() {
+    lazy(|| {
+        let clones = Arc::new(AtomicUsize::new(0));
+        let stream = iter_ok::<_, ()>(vec![Counted(clones.clone())]);
+        let cloneable = stream.cloneable();
+        let mut consumers = vec![cloneable.clone(); 100];
+        drop(cloneable);
+
+        // Polling one clone does not copy the item for the other 99 clones.
+        assert!(consumers[0].poll().unwrap().is_ready());
+        assert_eq!(clones.load(Ordering::SeqCst), 1);
+
+        // The last clone takes the item without cloning it.
+        for consumer in consumers.iter_mut().skip(1) {
+            assert!(consumer.poll().unwrap().is_ready());
+        }
+        assert_eq!(clones.load(Ordering::SeqCst), 99);
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn drop_clone() {
+    let stream = unfold(0, |i| Some(ok::<(usize, usize), u8>((i, i + 1)))).take(4);
+
+    let mut cloneable = stream.cloneable();
+    let cloneable2 = cloneable.clone();
+    let cloneable3 = cloneable.clone();
+
+    lazy(|| {
+        assert_eq!(cloneable.poll(), Ok(Async::Ready(Some(0))));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+    drop(cloneable2);
+
+    assert_eq!(cloneable.collect().wait().unwrap(), [1, 2, 3]);
+    assert_eq!(cloneable3.collect().wait().unwrap(), [0, 1, 2, 3]);
+}
+
+
+#[test]
+fn multi_thread() {
+    let (tx, rx) = futures::sync::mpsc::unbounded();
+    let cloneable = rx.cloneable();
+    let (res_tx, res_rx) = channel();
+
+    for _ in 0..4 {
+        let cloneable = cloneable.clone();
+        let res_tx = res_tx.clone();
+        thread::spawn(move || {
+            res_tx.send(cloneable.collect().wait().unwrap()).unwrap();
+        });
+    }
+    drop(cloneable);
+
+    thread::spawn(move || for i in 0..8 {
+        thread::sleep(Duration::from_millis(10));
+        tx.unbounded_send(i).unwrap();
+    });
+
+    for _ in 0..4 {
+        let res = res_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(res, [0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+}
+
+
+#[test]
+fn notify_on_drop() {
+    let (tx, rx) = futures::sync::mpsc::unbounded();
+    let cloneable = rx.cloneable();
+    let cloneable2 = cloneable.clone();
+    let (res_tx, res_rx) = channel();
+    let (parked_tx, parked_rx) = channel();
+
+    thread::spawn(move || {
+        res_tx.send(cloneable2.collect().wait().unwrap()).unwrap();
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    // "cloneable" polls original stream after "cloneable2" and then is dropped. So original
+    // stream will notify only "cloneable". "cloneable2" must be notified instead of it.
+    thread::spawn(move || {
+        let mut cloneable = cloneable;
+        lazy(|| {
+            assert_eq!(cloneable.poll(), Ok(Async::NotReady));
+            ok::<(), ()>(())
+        }).wait()
+            .unwrap();
+        drop(cloneable);
+        parked_tx.send(()).unwrap();
+    });
+    parked_rx.recv().unwrap();
+
+    tx.unbounded_send(0).unwrap();
+    drop(tx);
+
+    assert_eq!(res_rx.recv_timeout(Duration::from_secs(10)).unwrap(), [0]);
+}
+
+
+#[test]
+fn poisoned() {
+    let stream = iter_ok::<_, u8>(0..4).map(|i| if i == 1 { panic!("boom") } else { i });
+
+    let mut cloneable = stream.cloneable();
+    let cloneable2 = cloneable.clone();
+
+    let res = thread::spawn(move || {
+        lazy(|| {
+            assert_eq!(cloneable.poll(), Ok(Async::Ready(Some(0))));
+            let _ = cloneable.poll(); // Panics while polling original stream.
+            ok::<(), ()>(())
+        }).wait()
+    }).join();
+    assert!(res.is_err());
+
+    let res = cloneable2.then(Ok::<_, ()>).collect().wait().unwrap();
+    assert_eq!(res, [Ok(0), Err(SharedError::Poisoned)]);
+}
+
+
+#[test]
+fn cached() {
+    let stream = unfold(0, |i| Some(ok::<(usize, usize), u8>((i, i + 1)))).take(4);
+
+    let cached = stream.cached();
+    let cached2 = cached.clone();
+
+    assert_eq!(cached.collect().wait().unwrap(), [0, 1, 2, 3]);
+
+    let cached3 = cached2.clone();
+    assert_eq!(cached2.collect().wait().unwrap(), [0, 1, 2, 3]);
+    assert_eq!(cached3.collect().wait().unwrap(), [0, 1, 2, 3]);
+}
+
+
+#[test]
+fn replayable() {
+    let stream = unfold(0, |i| Some(ok::<(usize, usize), u8>((i, i + 1)))).take(4);
+
+    let mut replayable = stream.replayable(2);
+
+    lazy(|| {
+        assert_eq!(replayable.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(replayable.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(replayable.poll(), Ok(Async::Ready(Some(2))));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+
+    let replayable2 = replayable.clone();
+
+    assert_eq!(replayable.collect().wait().unwrap(), [3]);
+    assert_eq!(replayable2.collect().wait().unwrap(), [1, 2, 3]);
+}
+
+
+#[test]
+fn replay_finished_stream() {
+    let mut replayable = iter_ok::<_, ()>(0..4).replayable(1);
+
+    lazy(|| {
+        for i in 0..4 {
+            assert_eq!(replayable.poll(), Ok(Async::Ready(Some(i))));
+        }
+        assert_eq!(replayable.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+
+    assert_eq!(replayable.clone().collect().wait().unwrap(), [3]);
+}
+
+
+#[test]
+fn replay_errors_after_items() {
+    let msgs = vec![Ok(0), Err(0), Err(1), Ok(1), Err(2)];
+    let replayable = iter_result::<_, usize, u8>(msgs).replayable(1);
+
+    let all = replayable.clone().then(Ok::<_, ()>).collect().wait().unwrap();
+    let inner = SharedError::Inner;
+    assert_eq!(all, [Ok(0), Err(inner(0)), Err(inner(1)), Ok(1), Err(inner(2))]);
+
+    // Errors before the kept item are dropped, errors after it are replayed.
+    let late = replayable.clone().then(Ok::<_, ()>).collect().wait().unwrap();
+    assert_eq!(late, [Ok(1), Err(inner(2))]);
+}
+
+
+#[test]
+fn replay_large_history() {
+    // Reading an item must not scan the whole history, or this takes minutes.
+    let mut replayable = iter_ok::<_, ()>(0..200_000).replayable(10_000);
+
+    lazy(|| {
+        for i in 0..200_000 {
+            assert_eq!(replayable.poll(), Ok(Async::Ready(Some(i))));
+        }
+        assert_eq!(replayable.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+
+    let replayed = replayable.clone().collect().wait().unwrap();
+    assert_eq!(replayed, (190_000..200_000).collect::<Vec<_>>());
+}
+
+
+#[derive(Debug, PartialEq)]
+struct NotClone(usize);
+
+
+#[test]
+fn shared_items() {
+    let stream = iter_ok::<_, NotClone>(0..3).map(NotClone);
+
+    let shared = stream.shared_items();
+    let shared2 = shared.clone();
+
+    let res = shared.map(|i| i.0).collect().wait().unwrap();
+    let res2 = shared2.collect().wait().unwrap();
+
+    assert_eq!(res, [0, 1, 2]);
+    assert_eq!(res2[2], Arc::new(NotClone(2)));
+}
+
+
+#[test]
+fn shared_errors() {
+    let stream = iter_ok::<_, NotClone>(0..3).and_then(|i| Err::<usize, _>(NotClone(i)));
+
+    let shared = stream.shared_items();
+    let shared2 = shared.clone();
+
+    let err = shared.collect().wait().unwrap_err();
+    let err2 = shared2.collect().wait().unwrap_err();
+
+    assert_eq!(err.into_inner().unwrap(), Arc::new(NotClone(0)));
+    assert_eq!(err2.into_inner().unwrap(), Arc::new(NotClone(0)));
+}
+
+
+#[test]
+fn introspection() {
+    lazy(|| {
+        let mut rx1 = iter_ok::<_, u8>(0..2).cloneable();
+        let rx2 = rx1.clone();
+        assert_eq!(rx1.clone_count(), 2);
+        assert_eq!(rx1.dispatched(), 0);
+
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(rx1.queue_len(), 0);
+        assert_eq!(rx2.queue_len(), 1);
+        assert_eq!(rx1.dispatched(), 1);
+        assert!(!rx1.is_terminated());
+
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rx1.poll(), Ok(Async::Ready(None)));
+        assert_eq!(rx2.queue_len(), 3);
+        assert_eq!(rx2.dispatched(), 2);
+        assert!(rx2.is_terminated());
+
+        let debug = format!("{:?}", rx2);
+        assert!(debug.contains("clone_count: 2"));
+        assert!(debug.contains("queue_len: 3"));
+
+        drop(rx2);
+        assert_eq!(rx1.clone_count(), 1);
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn evict_by_backlog() {
+    lazy(|| {
+        let mut fast = iter_ok::<_, u8>(0..4).cloneable_evicting(Eviction::Backlog(2));
+        let mut slow = fast.clone();
+
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(fast.clone_count(), 2);
+
+        // `slow` gets 3 unread items.
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(fast.clone_count(), 1);
+        assert_eq!(slow.queue_len(), 0);
+
+        assert_eq!(slow.poll(), Err(SharedError::Evicted));
+        assert_eq!(slow.poll(), Ok(Async::Ready(None)));
+
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(3))));
+        assert_eq!(fast.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn evict_by_age() {
+    lazy(|| {
+        let mut fast = iter_ok::<_, u8>(0..3).cloneable_evicting(
+            Eviction::Age(Duration::from_millis(50)),
+        );
+        let mut slow = fast.clone();
+
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(fast.clone_count(), 2);
+
+        thread::sleep(Duration::from_millis(100));
+
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(fast.clone_count(), 1);
+
+        assert!(slow.poll().unwrap_err().is_evicted());
+
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(fast.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn clone_evicted() {
+    lazy(|| {
+        let items = (0..4).map(Arc::new).collect::<Vec<_>>();
+        let mut fast = iter_ok::<_, u8>(items.clone()).cloneable_evicting(Eviction::Backlog(1));
+        let mut slow = fast.clone();
+
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(items[0].clone()))));
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(items[1].clone()))));
+        assert_eq!(slow.poll(), Err(SharedError::Evicted));
+
+        // Clones of the evicted clone do not read items.
+        let mut slow2 = slow.clone();
+        let mut slow3 = slow.clone_lossy(1);
+        assert_eq!(fast.clone_count(), 1);
+        assert_eq!(slow2.poll(), Ok(Async::Ready(None)));
+        assert_eq!(slow3.poll(), Ok(Async::Ready(None)));
+
+        // So items are still released after `fast` reads them.
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(items[2].clone()))));
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(items[3].clone()))));
+        assert!(items.iter().all(|item| Arc::strong_count(item) == 1));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+struct DropFlag<S> {
+    stream: S,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl<S: Stream> Stream for DropFlag<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<S::Item>, S::Error> {
+        self.stream.poll()
+    }
+}
+
+impl<S> Drop for DropFlag<S> {
+    fn drop(&mut self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+
+#[test]
+fn drop_upstream_on_termination() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let stream = DropFlag {
+        stream: iter_ok::<_, u8>(0..2),
+        dropped: dropped.clone(),
+    };
+
+    let rx1 = stream.cloneable();
+    let rx2 = rx1.clone();
+
+    assert_eq!(rx1.collect().wait(), Ok(vec![0, 1]));
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+
+    let rx3 = rx2.clone();
+    assert_eq!(rx2.collect().wait(), Ok(vec![0, 1]));
+    assert_eq!(rx3.collect().wait(), Ok(vec![]));
+}
+
+
+type Victim = Arc<Mutex<Option<Box<dyn Any + Send>>>>;
+
+
+#[test]
+fn drop_clone_in_released_item() {
+    lazy(|| {
+        let victim: Victim = Arc::new(Mutex::new(None));
+        let mut rx1 = iter_ok::<_, ()>(vec![victim]).cloneable();
+        let rx2 = rx1.clone();
+
+        let item = match rx1.poll() {
+            Ok(Async::Ready(Some(item))) => item,
+            _ => panic!("an item is expected"),
+        };
+        // This clone does not keep the item because it is created after the item arrives.
+        *item.lock().unwrap() = Some(Box::new(rx1.clone()));
+        drop(item);
+
+        // Releasing the item drops the clone in it.
+        drop(rx2);
+        assert_eq!(rx1.clone_count(), 1);
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn drop_upstream_with_last_clone() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let stream = DropFlag {
+        stream: iter_ok::<_, u8>(0..4),
+        dropped: dropped.clone(),
+    };
+
+    lazy(|| {
+        let mut fast = stream.cloneable_evicting(Eviction::Backlog(1));
+        let mut slow = fast.clone();
+
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(fast.poll(), Ok(Async::Ready(Some(1))));
+
+        // Only evicted clone remains.
+        drop(fast);
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+
+        assert_eq!(slow.poll(), Err(SharedError::Evicted));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn downgrade() {
+    lazy(|| {
+        let mut rx1 = iter_ok::<_, u8>(0..3).cloneable();
+        let weak = rx1.downgrade();
+        assert_eq!(rx1.clone_count(), 1);
+
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(0))));
+
+        // A new clone reads items arriving after now.
+        let rx2 = weak.upgrade().unwrap();
+        assert_eq!(rx1.clone_count(), 2);
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rx2.queue_len(), 1);
+
+        drop(rx1);
+        drop(rx2);
+        assert!(weak.upgrade().is_none());
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn upgrade_after_last_clone() {
+    let published = iter_ok::<_, ()>(0..3).publish();
+    let rx = published.subscribe();
+    let weak = rx.downgrade();
+
+    // `published` can still create clones.
+    drop(rx);
+    let rx = weak.upgrade().unwrap();
+    assert_eq!(rx.clone_count(), 1);
+
+    drop(rx);
+    drop(published);
+    assert!(weak.upgrade().is_none());
+}
+
+
+#[test]
+fn publish() {
+    let polled = Arc::new(AtomicUsize::new(0));
+    let polled2 = polled.clone();
+    let stream = unfold(0, move |i| {
+        polled2.fetch_add(1, Ordering::SeqCst);
+        Some(ok::<(usize, usize), u8>((i, i + 1)))
+    });
+
+    lazy(move || {
+        let published = stream.publish();
+        let mut rx1 = published.subscribe();
+        let mut rx2 = published.subscribe();
+
+        // Original stream is not polled before connecting.
+        assert_eq!(rx1.poll(), Ok(Async::NotReady));
+        assert_eq!(polled.load(Ordering::SeqCst), 0);
+
+        let connection = published.connect();
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rx2.poll(), Ok(Async::Ready(Some(0))));
+
+        // Disconnect.
+        drop(connection);
+        assert_eq!(rx2.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rx2.poll(), Ok(Async::NotReady));
+        assert_eq!(polled.load(Ordering::SeqCst), 2);
+
+        let _connection = published.connect();
+        assert_eq!(rx2.poll(), Ok(Async::Ready(Some(2))));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn share_auto() {
+    let created = Arc::new(AtomicUsize::new(0));
+    let dropped = Arc::new(AtomicUsize::new(0));
+
+    let (created2, dropped2) = (created.clone(), dropped.clone());
+    let feed = ex_futures::stream::share_auto(move || {
+        created2.fetch_add(1, Ordering::SeqCst);
+        DropFlag {
+            stream: unfold(0, |i| Some(ok::<(usize, usize), u8>((i, i + 1)))),
+            dropped: dropped2.clone(),
+        }
+    });
+
+    let rx1 = feed.subscribe();
+    let rx2 = feed.clone().subscribe();
+    assert_eq!(created.load(Ordering::SeqCst), 1);
+    assert_eq!(rx1.clone_count(), 2);
+
+    assert_eq!(rx1.take(2).collect().wait(), Ok(vec![0, 1]));
+    assert_eq!(dropped.load(Ordering::SeqCst), 0);
+    drop(rx2);
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+
+    // Original stream is created again.
+    let rx3 = feed.subscribe();
+    assert_eq!(created.load(Ordering::SeqCst), 2);
+    assert_eq!(rx3.take(2).collect().wait(), Ok(vec![0, 1]));
+    assert_eq!(dropped.load(Ordering::SeqCst), 2);
+}
+
+
+
+#[test]
+fn share_auto_after_end() {
+    let created = Arc::new(AtomicUsize::new(0));
+
+    let created2 = created.clone();
+    let feed = ex_futures::stream::share_auto(move || {
+        created2.fetch_add(1, Ordering::SeqCst);
+        iter_ok::<_, ()>(0..2)
+    });
+
+    // The first subscriber is still living after original stream finishes.
+    let rx1 = feed.subscribe();
+    assert_eq!(rx1.clone().collect().wait(), Ok(vec![0, 1]));
+    assert!(rx1.is_terminated());
+
+    // Original stream is created again.
+    let rx2 = feed.subscribe();
+    assert_eq!(created.load(Ordering::SeqCst), 2);
+    assert_eq!(rx2.collect().wait(), Ok(vec![0, 1]));
+    drop(rx1);
+}
+
+#[test]
+fn lossy() {
+    lazy(|| {
+        let stream = unfold(0, |i| Some(ok::<(usize, usize), u8>((i, i + 1))));
+        let mut main = stream.cloneable_bounded(2);
+        let mut diag = main.clone_lossy(2);
+        assert!(!main.is_lossy());
+        assert!(diag.is_lossy());
+        assert!(diag.clone().is_lossy());
+
+        // The lossy clone does not throttle the main one.
+        for i in 0..5 {
+            assert_eq!(main.poll(), Ok(Async::Ready(Some(i))));
+        }
+        assert_eq!(diag.queue_len(), 2);
+        assert_eq!(diag.dropped(), 3);
+
+        assert_eq!(diag.poll(), Ok(Async::Ready(Some(3))));
+        assert_eq!(diag.poll(), Ok(Async::Ready(Some(4))));
+
+        // The main clone still throttles original stream.
+        let mut diag2 = diag.clone();
+        assert_eq!(diag2.poll(), Ok(Async::Ready(Some(5))));
+        assert_eq!(diag2.poll(), Ok(Async::Ready(Some(6))));
+        assert_eq!(diag2.poll(), Ok(Async::NotReady));
+        assert_eq!(main.poll(), Ok(Async::Ready(Some(5))));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn budget() {
+    let mut stream = iter_ok::<_, ()>(0..3).cloneable();
+    stream.set_budget(1);
+    let clone = stream.clone();
+
+    assert_eq!(stream.collect().wait(), Ok(vec![0, 1, 2]));
+    assert_eq!(clone.collect().wait(), Ok(vec![0, 1, 2]));
+}
+
+
+#[test]
+fn inherit_budget() {
+    let mut stream = iter_ok::<_, ()>(0..3).cloneable();
+    stream.set_budget(4);
+
+    // A clone created by a weak handle inherits the budget.
+    let weak = stream.downgrade();
+    let upgraded = weak.upgrade().unwrap();
+    assert!(format!("{:?}", upgraded).contains("budget: 4"));
+
+    let publisher = iter_ok::<_, ()>(0..3).publish();
+    publisher.set_budget(5);
+    let mut subscriber = publisher.subscribe();
+    assert!(format!("{:?}", subscriber).contains("budget: 5"));
+
+    // So does a subscriber after another subscriber sets its budget.
+    subscriber.set_budget(6);
+    assert!(format!("{:?}", publisher.subscribe()).contains("budget: 6"));
+}
+
+
+#[test]
+#[should_panic(expected = "budget must be positive")]
+fn zero_budget() {
+    iter_ok::<_, ()>(Vec::<usize>::new()).cloneable().set_budget(0);
+}
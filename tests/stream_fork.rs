@@ -2,14 +2,23 @@ extern crate ex_futures;
 extern crate futures;
 extern crate tokio_core;
 
-use futures::{Future, Stream};
-use futures::stream::unfold;
-use futures::future::ok;
+mod common;
 
-use ex_futures::StreamExt;
+use futures::{Future, Stream, Async};
+use futures::stream::{unfold, iter_ok, iter_result};
+use futures::future::{ok, lazy};
+use futures::executor;
+
+use ex_futures::{StreamExt, SharedError};
+use ex_futures::stream::{Fork, Side, Multicast, Branches};
+
+use common::flag;
 
 use tokio_core::reactor::Core;
 
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 
 
 #[test]
@@ -73,3 +82,516 @@ fn join() {
     assert_eq!(res1, [1, 3]);
     assert_eq!(res2, [0, 2]);
 }
+
+
+#[test]
+fn poisoned() {
+    let stream = iter_ok::<_, u8>(0..4).map(|i| if i == 2 { panic!("boom") } else { i });
+
+    let (mut even, odd) = stream.fork(|i| i % 2 == 0);
+
+    let res = std::thread::spawn(move || {
+        lazy(|| {
+            assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+            let _ = even.poll(); // Panics while polling original stream.
+            ok::<(), ()>(())
+        }).wait()
+    }).join();
+    assert!(res.is_err());
+
+    let res = odd.then(Ok::<_, ()>).collect().wait().unwrap();
+    assert_eq!(res, [Ok(1), Err(SharedError::Poisoned)]);
+}
+
+
+#[test]
+fn introspection() {
+    lazy(|| {
+        let (mut even, odd) = iter_ok::<_, u8>(0..3).fork(|i| i % 2 == 0);
+        assert_eq!(even.dispatched(), 0);
+
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(even.queue_len(), 0);
+        assert_eq!(odd.queue_len(), 1);
+        assert_eq!(odd.dispatched(), 3);
+        assert!(!odd.is_terminated());
+
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+        assert!(odd.is_terminated());
+        assert_eq!(odd.queue_len(), 2);
+
+        let debug = format!("{:?}", odd);
+        assert!(debug.contains("queue_len: 2"));
+        assert!(debug.contains("dispatched: 3"));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn fork_n() {
+    let stream = iter_ok::<_, u8>(0..7usize).and_then(|i| if i == 5 { Err(5) } else { Ok(i) });
+
+    let mut branches = stream.fork_n(3, |i| i % 3);
+    assert_eq!(branches.len(), 3);
+    assert_eq!(branches[2].index(), 2);
+
+    let rem2 = branches.pop().unwrap();
+    let rem1 = branches.pop().unwrap();
+    let rem0 = branches.pop().unwrap();
+
+    let collect = |fork: Fork<_, _>| fork.then(Ok::<_, ()>).collect().wait().unwrap();
+
+    assert_eq!(collect(rem0), [Ok(0), Ok(3), Err(SharedError::Inner(5)), Ok(6)]);
+    assert_eq!(collect(rem1), [Ok(1), Ok(4), Err(SharedError::Inner(5))]);
+    assert_eq!(collect(rem2), [Ok(2), Err(SharedError::Inner(5))]);
+}
+
+
+#[test]
+fn fork_n_out_of_range() {
+    let mut branches = iter_ok::<_, u8>(0..3usize).fork_n(2, |i| *i);
+    let mut second = branches.pop().unwrap();
+    let mut first = branches.pop().unwrap();
+
+    let res = std::thread::spawn(move || {
+        lazy(|| {
+            assert_eq!(first.poll(), Ok(Async::Ready(Some(0))));
+            let _ = first.poll(); // Routes 1 to the second branch.
+            let _ = first.poll(); // Panics while routing 2.
+            ok::<(), ()>(())
+        }).wait()
+    }).join();
+    assert!(res.is_err());
+
+    lazy(|| {
+        assert_eq!(second.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(second.poll(), Err(SharedError::Poisoned));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn out_of_range_routes_nothing() {
+    let mut branches = iter_ok::<_, u8>(0..2usize).fork_n(2, |i| if *i == 0 {
+        Branches::from(1)
+    } else {
+        Branches::from(1).with(2)
+    });
+    let mut second = branches.pop().unwrap();
+    let mut first = branches.pop().unwrap();
+
+    let res = std::thread::spawn(move || {
+        lazy(|| {
+            let _ = first.poll(); // Routes 0 to the second branch, and panics while routing 1.
+            ok::<(), ()>(())
+        }).wait()
+    }).join();
+    assert!(res.is_err());
+
+    // The valid branch of 1 does not receive it either.
+    assert_eq!(second.dispatched(), 1);
+    lazy(|| {
+        assert_eq!(second.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(second.poll(), Err(SharedError::Poisoned));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn multicast() {
+    let stream = iter_ok::<_, u8>(0..6usize);
+
+    let (left, right) = stream.fork(|i| match i % 3 {
+        0 => Multicast::Both,
+        1 => Multicast::Left,
+        _ => Multicast::Neither,
+    });
+
+    let (left, right) = left.collect().join(right.collect()).wait().unwrap();
+    assert_eq!(left, [0, 1, 3, 4]);
+    assert_eq!(right, [0, 3]);
+}
+
+
+#[test]
+fn fork_n_branches() {
+    let stream = iter_ok::<_, u8>(0..4usize);
+
+    let mut branches = stream.fork_n(3, |i| if *i == 0 {
+        (0..3).collect::<Branches>()
+    } else {
+        Branches::from(*i % 3).with(2)
+    });
+    let third = branches.pop().unwrap();
+    let second = branches.pop().unwrap();
+    let first = branches.pop().unwrap();
+
+    assert_eq!(third.collect().wait(), Ok(vec![0, 1, 2, 3]));
+    assert_eq!(second.collect().wait(), Ok(vec![0, 1]));
+    assert_eq!(first.collect().wait(), Ok(vec![0, 3]));
+}
+
+
+#[test]
+fn branches() {
+    let mut branches = Branches::new();
+    assert!(branches.is_empty());
+
+    branches.insert(3);
+    branches.insert(130);
+    branches.insert(64);
+    assert_eq!(branches.len(), 3);
+    assert!(branches.contains(64));
+    assert!(!branches.contains(65));
+    assert_eq!(branches.iter().collect::<Vec<_>>(), [3, 64, 130]);
+}
+
+
+#[test]
+fn non_clone_items() {
+    #[derive(Debug, PartialEq)]
+    struct NotClone(usize);
+
+    let stream = iter_ok::<_, u8>(0..4).map(NotClone);
+    let (even, odd) = stream.fork(|i| i.0 % 2 == 0);
+
+    let (even, odd) = even.collect().join(odd.collect()).wait().unwrap();
+    assert_eq!(even, [NotClone(0), NotClone(2)]);
+    assert_eq!(odd, [NotClone(1), NotClone(3)]);
+}
+
+
+#[test]
+fn side_router() {
+    #[derive(Debug, PartialEq)]
+    struct NotClone(usize);
+
+    // A router type of users converting into `Side`.
+    enum Parity {
+        Even,
+        Odd,
+    }
+
+    impl From<Parity> for Side {
+        fn from(parity: Parity) -> Side {
+            match parity {
+                Parity::Even => Side::Left,
+                Parity::Odd => Side::Right,
+            }
+        }
+    }
+
+    let stream = iter_ok::<_, u8>(0..4).map(NotClone);
+    let (even, odd) = stream.fork(|i| if i.0 % 2 == 0 { Parity::Even } else { Parity::Odd });
+
+    let (even, odd) = even.collect().join(odd.collect()).wait().unwrap();
+    assert_eq!(even, [NotClone(0), NotClone(2)]);
+    assert_eq!(odd, [NotClone(1), NotClone(3)]);
+
+    let stream = iter_ok::<_, u8>(0..2).map(NotClone);
+    let (left, right) = stream.fork(|_| Side::Right);
+
+    let (left, right) = left.collect().join(right.collect()).wait().unwrap();
+    assert_eq!(left, []);
+    assert_eq!(right, [NotClone(0), NotClone(1)]);
+}
+
+
+#[test]
+fn bounded() {
+    lazy(|| {
+        let (mut even, mut odd) = iter_ok::<_, u8>(0..6).fork_bounded(1, |i| i % 2 == 0);
+
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+
+        // `3` does not fit into the queue of `odd` which already holds `1`.
+        assert_eq!(even.poll(), Ok(Async::NotReady));
+        assert_eq!(odd.queue_len(), 1);
+        assert_eq!(even.dispatched(), 4);
+
+        assert_eq!(odd.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(odd.queue_len(), 1);
+
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(4))));
+        assert_eq!(even.poll(), Ok(Async::NotReady));
+
+        assert_eq!(odd.poll(), Ok(Async::Ready(Some(3))));
+        assert_eq!(odd.poll(), Ok(Async::Ready(Some(5))));
+        assert_eq!(odd.poll(), Ok(Async::Ready(None)));
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn bounded_wakes_blocked_branch() {
+    let (tx, rx) = futures::sync::mpsc::unbounded::<usize>();
+    for i in 0..4 {
+        tx.unbounded_send(i).unwrap();
+    }
+    drop(tx);
+
+    let (left, right) = rx.fork_bounded(1, |i| *i < 2);
+
+    // `right` can not read anything until `left` reads its queue.
+    let (right, left) = right.collect().join(left.collect()).wait().unwrap();
+    assert_eq!(left, [0, 1]);
+    assert_eq!(right, [2, 3]);
+}
+
+
+#[test]
+fn discard_items_of_dropped_branch() {
+    lazy(|| {
+        let (mut even, odd) = iter_ok::<_, u8>(0..6).fork(|i| i % 2 == 0);
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(odd.queue_len(), 1);
+
+        drop(odd);
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(4))));
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+        assert_eq!(even.dispatched(), 6);
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn fallback() {
+    let mut branches = iter_ok::<_, u8>(0..6).fork_n(3, |i| i % 3);
+    let rem2 = branches.pop().unwrap();
+    let rem1 = branches.pop().unwrap();
+    let rem0 = branches.pop().unwrap();
+
+    rem0.set_fallback();
+    drop(rem1);
+
+    assert_eq!(rem0.collect().wait(), Ok(vec![0, 1, 3, 4]));
+    assert_eq!(rem2.collect().wait(), Ok(vec![2, 5]));
+}
+
+
+#[test]
+fn dropped_branch_unblocks_bounded_fork() {
+    lazy(|| {
+        let (mut left, right) = iter_ok::<_, u8>(0..4).fork_bounded(1, |i| *i >= 2);
+        assert_eq!(left.poll(), Ok(Async::NotReady));
+
+        drop(right);
+        assert_eq!(left.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(left.poll(), Ok(Async::Ready(Some(3))));
+        assert_eq!(left.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+struct DropFlag<S> {
+    stream: S,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl<S: Stream> Stream for DropFlag<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<S::Item>, S::Error> {
+        self.stream.poll()
+    }
+}
+
+impl<S> Drop for DropFlag<S> {
+    fn drop(&mut self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+
+#[test]
+fn drop_upstream_with_last_branch() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let stream = DropFlag {
+        stream: iter_ok::<_, u8>(0..4),
+        dropped: dropped.clone(),
+    };
+
+    let (left, right) = stream.fork(|i| i % 2 == 0);
+    drop(left);
+    assert_eq!(dropped.load(Ordering::SeqCst), 0);
+
+    drop(right);
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+}
+
+
+#[derive(Debug, PartialEq)]
+struct NotClone(usize);
+
+
+#[test]
+fn errors_to_branch() {
+    lazy(|| {
+        let stream = iter_result(vec![Ok(0), Err(NotClone(1)), Ok(2)]);
+        let (mut left, mut right) = stream.fork_with_errors(|_| true, 1);
+
+        assert_eq!(left.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(left.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(left.poll(), Ok(Async::Ready(None)));
+        assert_eq!(right.poll(), Err(SharedError::Inner(NotClone(1))));
+        assert_eq!(right.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn error_router() {
+    lazy(|| {
+        let stream = iter_result(vec![Err(NotClone(0)), Err(NotClone(3)), Ok(1)]);
+        let mut branches = stream.fork_n_with_errors(3, |i| *i, |e: &NotClone| e.0 % 3);
+        let mut rem2 = branches.pop().unwrap();
+        let mut rem1 = branches.pop().unwrap();
+        let mut rem0 = branches.pop().unwrap();
+
+        assert_eq!(rem1.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rem0.poll(), Err(SharedError::Inner(NotClone(0))));
+        assert_eq!(rem0.poll(), Err(SharedError::Inner(NotClone(3))));
+        assert_eq!(rem2.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn error_stream() {
+    lazy(|| {
+        let stream = iter_result(vec![Ok(0), Err(NotClone(1)), Ok(2), Err(NotClone(3))]);
+        let (mut branches, mut errors) = stream.fork_n_with_error_stream(2, |i| i % 2);
+        let odd = branches.pop().unwrap();
+        let mut even = branches.pop().unwrap();
+
+        assert_eq!(errors.poll(), Ok(Async::Ready(Some(NotClone(1)))));
+        assert_eq!(even.queue_len(), 1);
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+        assert_eq!(odd.queue_len(), 1);
+        assert_eq!(errors.poll(), Ok(Async::Ready(Some(NotClone(3)))));
+        assert_eq!(errors.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn notify_branch_receiving_item() {
+    let (tx, rx) = futures::sync::mpsc::unbounded::<usize>();
+    let (left, right) = rx.fork(|i| i % 2 == 0);
+    let mut left = executor::spawn(left);
+    let mut right = executor::spawn(right);
+    let (left_flag, right_flag) = (flag(), flag());
+
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::NotReady));
+    // Original stream notifies only `left` from now.
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::NotReady));
+
+    tx.unbounded_send(1).unwrap();
+    assert!(left_flag.0.load(Ordering::SeqCst));
+    assert!(!right_flag.0.load(Ordering::SeqCst));
+
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::NotReady));
+    assert!(right_flag.0.load(Ordering::SeqCst));
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::Ready(Some(1))));
+
+    right_flag.0.store(false, Ordering::SeqCst);
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::NotReady));
+    drop(tx);
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::Ready(None)));
+    assert!(right_flag.0.load(Ordering::SeqCst));
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::Ready(None)));
+}
+
+
+#[test]
+fn notify_others_on_drop() {
+    let (tx, rx) = futures::sync::mpsc::unbounded::<usize>();
+    let (left, right) = rx.fork(|i| i % 2 == 0);
+    let mut left = executor::spawn(left);
+    let mut right = executor::spawn(right);
+    let (left_flag, right_flag) = (flag(), flag());
+
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::NotReady));
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::NotReady));
+
+    // `right` must poll original stream instead of `left`.
+    drop(left);
+    assert!(right_flag.0.load(Ordering::SeqCst));
+
+    tx.unbounded_send(1).unwrap();
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::Ready(Some(1))));
+}
+
+
+#[test]
+fn yield_after_budget() {
+    let (mut left, right) = iter_ok::<_, ()>(0..4).fork(|_| false);
+    left.set_budget(2);
+    let mut left = executor::spawn(left);
+    let left_flag = flag();
+
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::NotReady));
+    assert!(left_flag.0.load(Ordering::SeqCst));
+    assert_eq!(right.queue_len(), 2);
+
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::NotReady));
+    assert_eq!(right.queue_len(), 4);
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::Ready(None)));
+}
+
+
+#[test]
+fn long_run_of_other_branch() {
+    let (left, right) = iter_ok::<_, ()>(0..100_000).fork(|_| false);
+
+    assert_eq!(left.collect().wait(), Ok(vec![]));
+    assert_eq!(right.collect().wait().map(|items| items.len()), Ok(100_000));
+}
+
+
+#[test]
+fn multi_thread() {
+    let (tx, rx) = futures::sync::mpsc::unbounded::<usize>();
+    let (even, odd) = rx.fork(|i| i % 2 == 0);
+
+    let odd = std::thread::spawn(move || odd.collect().wait());
+    let even = std::thread::spawn(move || even.collect().wait());
+
+    for i in 0..1000 {
+        if i % 100 == 0 {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        tx.unbounded_send(i).unwrap();
+    }
+    drop(tx);
+
+    let even = even.join().unwrap().unwrap();
+    let odd = odd.join().unwrap().unwrap();
+    assert_eq!(even, (0..1000).filter(|i| i % 2 == 0).collect::<Vec<_>>());
+    assert_eq!(odd, (0..1000).filter(|i| i % 2 == 1).collect::<Vec<_>>());
+}
@@ -0,0 +1,186 @@
+extern crate ex_futures;
+extern crate futures;
+
+mod common;
+
+use futures::{Future, Stream, Async};
+use futures::stream::iter_ok;
+use futures::future::{ok, lazy};
+use futures::executor;
+
+use ex_futures::StreamExt;
+
+use common::{drop_on_poll, flag};
+
+use std::sync::atomic::Ordering;
+
+
+
+#[test]
+fn group_by() {
+    let stream = iter_ok::<_, u8>(vec![1, 12, 3, 25, 14, 5]);
+
+    let groups = stream.group_by(|i| i / 10).collect().wait().unwrap();
+    assert_eq!(groups.iter().map(|&(k, _)| k).collect::<Vec<_>>(), [0, 1, 2]);
+
+    let res = groups
+        .into_iter()
+        .map(|(_, group)| group.collect().wait().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(res, [vec![1, 3, 5], vec![12, 14], vec![25]]);
+}
+
+
+#[test]
+fn interleaved() {
+    lazy(|| {
+        let stream = iter_ok::<_, u8>(vec![1, 2, 3, 4]);
+        let mut groups = stream.group_by(|i| i % 2);
+
+        let (odd_key, mut odd) = match groups.poll() {
+            Ok(Async::Ready(Some(group))) => group,
+            _ => panic!(),
+        };
+        assert_eq!(odd_key, 1);
+        assert_eq!(*odd.key(), 1);
+
+        // Polling a group creates another group.
+        assert_eq!(odd.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(odd.poll(), Ok(Async::Ready(Some(3))));
+
+        let (even_key, even) = match groups.poll() {
+            Ok(Async::Ready(Some(group))) => group,
+            _ => panic!(),
+        };
+        assert_eq!(even_key, 0);
+
+        assert_eq!(odd.poll(), Ok(Async::Ready(None)));
+        assert!(matches!(groups.poll(), Ok(Async::Ready(None))));
+        assert_eq!(even.collect().wait(), Ok(vec![2, 4]));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn error() {
+    let stream = iter_ok::<_, u8>(vec![1, 2, 3]).and_then(|i| if i == 2 { Err(i) } else { Ok(i) });
+    let mut groups = stream.group_by(|i| *i).wait();
+
+    let (_, group) = groups.next().unwrap().unwrap();
+    assert_eq!(groups.next().unwrap().err(), Some(2));
+    assert_eq!(group.wait().collect::<Vec<_>>(), [Ok(1), Err(2)]);
+}
+
+
+#[test]
+fn drop_group() {
+    lazy(|| {
+        let stream = iter_ok::<_, u8>(vec![1, 1, 2]);
+        let mut groups = stream.group_by(|i| *i);
+
+        let (_, mut one) = match groups.poll() {
+            Ok(Async::Ready(Some(group))) => group,
+            _ => panic!(),
+        };
+        assert_eq!(one.poll(), Ok(Async::Ready(Some(1))));
+        drop(one);
+
+        // The key appears again as a new group.
+        let keys = groups
+            .map(|(key, group)| (key, group.collect().wait().unwrap()))
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(keys, [(1, vec![1]), (2, vec![2])]);
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn drop_group_while_polling() {
+    lazy(|| {
+        let (stream, victim) = drop_on_poll(iter_ok::<_, ()>(0..4));
+        let mut groups = stream.group_by(|i| i % 2);
+
+        let (_, mut even) = match groups.poll() {
+            Ok(Async::Ready(Some(group))) => group,
+            _ => panic!(),
+        };
+        let (_, odd) = match groups.poll() {
+            Ok(Async::Ready(Some(group))) => group,
+            _ => panic!(),
+        };
+        *victim.borrow_mut() = Some(Box::new(odd));
+
+        // Original stream drops `odd` here.
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+
+        // The key appears again as a new group.
+        let (key, odd) = match groups.poll() {
+            Ok(Async::Ready(Some(group))) => group,
+            _ => panic!(),
+        };
+        assert_eq!(key, 1);
+        assert_eq!(odd.collect().wait(), Ok(vec![3]));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn yield_after_budget() {
+    // Only the first item goes to `zero`, and the others go to another group.
+    let mut groups = iter_ok::<_, ()>(0..100).group_by(|i| *i == 0).wait();
+    let (_, zero) = groups.next().unwrap().unwrap();
+    let mut zero = executor::spawn(zero);
+    let flag = flag();
+
+    assert_eq!(zero.poll_stream_notify(&flag, 0), Ok(Async::Ready(Some(0))));
+
+    // Each poll reads at most 32 items of other groups, and then yields.
+    for _ in 0..3 {
+        flag.0.store(false, Ordering::SeqCst);
+        assert_eq!(zero.poll_stream_notify(&flag, 0), Ok(Async::NotReady));
+        assert!(flag.0.load(Ordering::SeqCst));
+    }
+    assert_eq!(zero.poll_stream_notify(&flag, 0), Ok(Async::Ready(None)));
+
+    let (_, others) = groups.next().unwrap().unwrap();
+    assert_eq!(others.collect().wait().map(|items| items.len()), Ok(99));
+}
+
+
+#[test]
+fn notify_others_on_drop() {
+    let (tx, rx) = futures::sync::mpsc::unbounded::<usize>();
+    let mut groups = executor::spawn(rx.group_by(|i| *i));
+    let (groups_flag, one_flag) = (flag(), flag());
+
+    tx.unbounded_send(1).unwrap();
+    let mut one = match groups.poll_stream_notify(&groups_flag, 0) {
+        Ok(Async::Ready(Some((_, group)))) => executor::spawn(group),
+        _ => panic!(),
+    };
+    assert!(groups.poll_stream_notify(&groups_flag, 0).unwrap().is_not_ready());
+
+    // `one` polls original stream last, so only `one` is notified by it.
+    assert_eq!(one.poll_stream_notify(&one_flag, 0), Ok(Async::Ready(Some(1))));
+    assert_eq!(one.poll_stream_notify(&one_flag, 0), Ok(Async::NotReady));
+
+    // `groups` must poll original stream instead of `one`.
+    drop(one);
+    assert!(groups_flag.0.load(Ordering::SeqCst));
+
+    tx.unbounded_send(2).unwrap();
+    match groups.poll_stream_notify(&groups_flag, 0) {
+        Ok(Async::Ready(Some((key, _)))) => assert_eq!(key, 2),
+        _ => panic!(),
+    }
+}
@@ -0,0 +1,68 @@
+extern crate ex_futures;
+extern crate futures;
+
+use futures::{Future, Stream, Async};
+use futures::stream::iter_result;
+use futures::future::{ok, lazy};
+
+use ex_futures::{StreamExt, SharedError};
+use ex_futures::stream::Either;
+
+
+
+#[test]
+fn split_either() {
+    lazy(|| {
+        let stream = iter_result::<_, usize, u8>(vec![Ok(0), Ok(1), Ok(2), Err(3), Ok(4)]);
+        let (mut even, mut odd) = stream.split_either(|i| if i % 2 == 0 {
+            Either::Left(i)
+        } else {
+            Either::Right(i.to_string())
+        });
+
+        assert_eq!(odd.poll(), Ok(Async::Ready(Some("1".to_string()))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(even.poll(), Err(SharedError::Inner(3)));
+        assert_eq!(odd.poll(), Err(SharedError::Inner(3)));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(4))));
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+        assert_eq!(odd.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn partition_result() {
+    let stream = iter_result::<_, Result<u8, String>, ()>(vec![
+        Ok(Ok(0)),
+        Ok(Err("a".to_string())),
+        Ok(Ok(1)),
+    ]);
+    let (oks, errs) = stream.partition_result();
+
+    assert_eq!(errs.collect().wait(), Ok(vec!["a".to_string()]));
+    assert_eq!(oks.collect().wait(), Ok(vec![0, 1]));
+}
+
+
+#[test]
+fn unzip() {
+    lazy(|| {
+        let stream = iter_result::<_, (u8, char), ()>(vec![Ok((0, 'a')), Ok((1, 'b'))]);
+        let (mut numbers, mut letters) = stream.unzip();
+
+        assert_eq!(numbers.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(numbers.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(letters.poll(), Ok(Async::Ready(Some('a'))));
+        assert_eq!(letters.poll(), Ok(Async::Ready(Some('b'))));
+        assert_eq!(letters.poll(), Ok(Async::Ready(None)));
+        assert_eq!(numbers.poll(), Ok(Async::Ready(None)));
+
+        assert!(format!("{:?}", numbers).contains("terminated: true"));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
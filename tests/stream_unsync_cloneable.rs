@@ -2,14 +2,23 @@ extern crate ex_futures;
 extern crate futures;
 extern crate tokio_core;
 
-use futures::{Future, Stream};
-use futures::stream::unfold;
-use futures::future::ok;
+mod common;
+
+use futures::{Future, Stream, Async};
+use futures::stream::{unfold, iter_ok, iter_result};
+use futures::future::{ok, lazy};
 
 use ex_futures::StreamExt;
+use ex_futures::stream::UnsyncCloneable;
+
+use common::drop_on_poll;
 
 use tokio_core::reactor::Core;
 
+use std::sync::Arc;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 
 
 #[test]
@@ -76,3 +85,503 @@ fn zip() {
     assert_eq!(res1, [0, 1, 2, 3]);
     assert_eq!(res2, [0, 1, 2, 3]);
 }
+
+
+#[test]
+fn bounded() {
+    let pulled = Arc::new(AtomicUsize::new(0));
+    let pulled2 = pulled.clone();
+    let stream = iter_ok::<_, u8>(0..8).inspect(move |_| {
+        pulled2.fetch_add(1, Ordering::SeqCst);
+    });
+
+    let mut cloneable = stream.unsync_cloneable_bounded(2);
+    let mut cloneable2 = cloneable.clone();
+
+    lazy(|| {
+        assert_eq!(cloneable.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(cloneable.poll(), Ok(Async::Ready(Some(1))));
+        // Queue of "cloneable2" is full.
+        assert_eq!(cloneable.poll(), Ok(Async::NotReady));
+        assert_eq!(pulled.load(Ordering::SeqCst), 2);
+
+        assert_eq!(cloneable2.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(cloneable.poll(), Ok(Async::Ready(Some(2))));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+
+    let (res1, res2) = cloneable.collect().join(cloneable2.collect()).wait().unwrap();
+    assert_eq!(res1, [3, 4, 5, 6, 7]);
+    assert_eq!(res2, [1, 2, 3, 4, 5, 6, 7]);
+}
+
+
+#[test]
+fn cached() {
+    let stream = unfold(0, |i| Some(ok::<(usize, usize), u8>((i, i + 1)))).take(4);
+
+    let cached = stream.unsync_cached();
+    let cached2 = cached.clone();
+
+    assert_eq!(cached.collect().wait().unwrap(), [0, 1, 2, 3]);
+
+    let cached3 = cached2.clone();
+    assert_eq!(cached2.collect().wait().unwrap(), [0, 1, 2, 3]);
+    assert_eq!(cached3.collect().wait().unwrap(), [0, 1, 2, 3]);
+}
+
+
+#[test]
+fn replayable() {
+    let stream = unfold(0, |i| Some(ok::<(usize, usize), u8>((i, i + 1)))).take(4);
+
+    let mut replayable = stream.unsync_replayable(2);
+
+    lazy(|| {
+        assert_eq!(replayable.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(replayable.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(replayable.poll(), Ok(Async::Ready(Some(2))));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+
+    let replayable2 = replayable.clone();
+
+    assert_eq!(replayable.collect().wait().unwrap(), [3]);
+    assert_eq!(replayable2.collect().wait().unwrap(), [1, 2, 3]);
+}
+
+
+#[test]
+fn replay_finished_stream() {
+    let mut replayable = iter_ok::<_, ()>(0..4).unsync_replayable(1);
+
+    lazy(|| {
+        for i in 0..4 {
+            assert_eq!(replayable.poll(), Ok(Async::Ready(Some(i))));
+        }
+        assert_eq!(replayable.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+
+    assert_eq!(replayable.clone().collect().wait().unwrap(), [3]);
+}
+
+
+#[test]
+fn replay_errors_after_items() {
+    let msgs = vec![Ok(0), Err(0), Err(1), Ok(1), Err(2)];
+    let replayable = iter_result::<_, usize, u8>(msgs).unsync_replayable(1);
+
+    let all = replayable.clone().then(Ok::<_, ()>).collect().wait().unwrap();
+    assert_eq!(all, [Ok(0), Err(0), Err(1), Ok(1), Err(2)]);
+
+    // Errors before the kept item are dropped, errors after it are replayed.
+    let late = replayable.clone().then(Ok::<_, ()>).collect().wait().unwrap();
+    assert_eq!(late, [Ok(1), Err(2)]);
+}
+
+
+#[derive(Debug, PartialEq)]
+struct NotClone(usize);
+
+
+#[test]
+fn clone_without_bounds() {
+    // Cloning a handle must not require `Clone` items.
+    fn clone_handle<S: Stream>(cloneable: &UnsyncCloneable<S>) -> UnsyncCloneable<S> {
+        cloneable.clone()
+    }
+
+    let cloneable = iter_ok::<_, ()>(0..3).unsync_cloneable();
+    let cloneable2 = clone_handle(&cloneable);
+
+    assert_eq!(cloneable.collect().wait().unwrap(), [0, 1, 2]);
+    assert_eq!(cloneable2.collect().wait().unwrap(), [0, 1, 2]);
+}
+
+
+#[test]
+fn shared_items() {
+    let stream = iter_ok::<_, NotClone>(0..3).map(NotClone);
+
+    let shared = stream.unsync_shared_items();
+    let shared2 = shared.clone();
+
+    let res = shared.map(|i| i.0).collect().wait().unwrap();
+    let res2 = shared2.collect().wait().unwrap();
+
+    assert_eq!(res, [0, 1, 2]);
+    assert_eq!(res2[2], Rc::new(NotClone(2)));
+}
+
+
+#[test]
+fn shared_errors() {
+    let stream = iter_ok::<_, NotClone>(0..3).and_then(|i| Err::<usize, _>(NotClone(i)));
+
+    let shared = stream.unsync_shared_items();
+    let shared2 = shared.clone();
+
+    let err = shared.collect().wait().unwrap_err();
+    let err2 = shared2.collect().wait().unwrap_err();
+
+    assert_eq!(err, Rc::new(NotClone(0)));
+    assert_eq!(err2, Rc::new(NotClone(0)));
+}
+
+
+#[test]
+fn introspection() {
+    lazy(|| {
+        let mut rx1 = iter_ok::<_, u8>(0..2).unsync_cloneable();
+        let rx2 = rx1.clone();
+        assert_eq!(rx1.clone_count(), 2);
+        assert_eq!(rx1.dispatched(), 0);
+
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(rx1.queue_len(), 0);
+        assert_eq!(rx2.queue_len(), 1);
+        assert_eq!(rx1.dispatched(), 1);
+        assert!(!rx1.is_terminated());
+
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rx1.poll(), Ok(Async::Ready(None)));
+        assert_eq!(rx2.queue_len(), 3);
+        assert_eq!(rx2.dispatched(), 2);
+        assert!(rx2.is_terminated());
+
+        let debug = format!("{:?}", rx2);
+        assert!(debug.contains("clone_count: 2"));
+        assert!(debug.contains("queue_len: 3"));
+
+        drop(rx2);
+        assert_eq!(rx1.clone_count(), 1);
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+struct DropFlag<S> {
+    stream: S,
+    dropped: Rc<AtomicUsize>,
+}
+
+impl<S: Stream> Stream for DropFlag<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<S::Item>, S::Error> {
+        self.stream.poll()
+    }
+}
+
+impl<S> Drop for DropFlag<S> {
+    fn drop(&mut self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+
+#[test]
+fn drop_upstream_on_termination() {
+    let dropped = Rc::new(AtomicUsize::new(0));
+    let stream = DropFlag {
+        stream: iter_ok::<_, u8>(0..2),
+        dropped: dropped.clone(),
+    };
+
+    let rx1 = stream.unsync_cloneable();
+    let rx2 = rx1.clone();
+
+    assert_eq!(rx1.collect().wait(), Ok(vec![0, 1]));
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+
+    let rx3 = rx2.clone();
+    assert_eq!(rx2.collect().wait(), Ok(vec![0, 1]));
+    assert_eq!(rx3.collect().wait(), Ok(vec![]));
+}
+
+
+#[test]
+fn prune_receiver_on_drop() {
+    let rx1 = iter_ok::<_, u8>(0..2).unsync_cloneable_bounded(1);
+    let rx2 = rx1.clone();
+    let rx3 = rx1.clone();
+    assert_eq!(rx1.clone_count(), 3);
+
+    drop(rx2);
+    assert_eq!(rx1.clone_count(), 2);
+
+    drop(rx3);
+    // Dropped clones do not throttle others.
+    assert_eq!(rx1.collect().wait(), Ok(vec![0, 1]));
+}
+
+
+#[test]
+fn drop_clone_while_polling() {
+    lazy(|| {
+        let (stream, victim) = drop_on_poll(iter_ok::<_, ()>(0..3));
+        let mut rx1 = stream.unsync_cloneable_bounded(1);
+        *victim.borrow_mut() = Some(Box::new(rx1.clone()));
+
+        // The clone dropped by original stream does not throttle others.
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rx1.clone_count(), 1);
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn downgrade() {
+    lazy(|| {
+        let mut rx1 = iter_ok::<_, u8>(0..3).unsync_cloneable();
+        let weak = rx1.downgrade();
+        assert_eq!(rx1.clone_count(), 1);
+
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(0))));
+
+        // A new clone reads items arriving after now.
+        let rx2 = weak.upgrade().unwrap();
+        assert_eq!(rx1.clone_count(), 2);
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rx2.queue_len(), 1);
+
+        drop(rx1);
+        drop(rx2);
+        assert!(weak.upgrade().is_none());
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn upgrade_after_last_clone() {
+    let published = iter_ok::<_, ()>(0..3).unsync_publish();
+    let rx = published.subscribe();
+    let weak = rx.downgrade();
+
+    // `published` can still create clones.
+    drop(rx);
+    let rx = weak.upgrade().unwrap();
+    assert_eq!(rx.clone_count(), 1);
+
+    drop(rx);
+    drop(published);
+    assert!(weak.upgrade().is_none());
+}
+
+
+#[test]
+fn publish() {
+    let polled = Arc::new(AtomicUsize::new(0));
+    let polled2 = polled.clone();
+    let stream = unfold(0, move |i| {
+        polled2.fetch_add(1, Ordering::SeqCst);
+        Some(ok::<(usize, usize), u8>((i, i + 1)))
+    });
+
+    lazy(move || {
+        let published = stream.unsync_publish();
+        let mut rx1 = published.subscribe();
+        let mut rx2 = published.subscribe();
+
+        // Original stream is not polled before connecting.
+        assert_eq!(rx1.poll(), Ok(Async::NotReady));
+        assert_eq!(polled.load(Ordering::SeqCst), 0);
+
+        let connection = published.connect();
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(rx1.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rx2.poll(), Ok(Async::Ready(Some(0))));
+
+        // Disconnect.
+        drop(connection);
+        assert_eq!(rx2.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rx2.poll(), Ok(Async::NotReady));
+        assert_eq!(polled.load(Ordering::SeqCst), 2);
+
+        let _connection = published.connect();
+        assert_eq!(rx2.poll(), Ok(Async::Ready(Some(2))));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn publish_to_spawned_tasks() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+
+    let (tx, rx) = futures::sync::mpsc::unbounded();
+    std::thread::spawn(move || for i in 0..4 {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        tx.unbounded_send(i).unwrap();
+    });
+
+    let published = rx.unsync_publish();
+
+    // Only one of the tasks is notified by original stream.
+    let (done1, res1) = futures::sync::oneshot::channel();
+    handle.spawn(published.subscribe().collect().map(|items| done1.send(items).unwrap()));
+    let (done2, res2) = futures::sync::oneshot::channel();
+    handle.spawn(published.subscribe().collect().map(|items| done2.send(items).unwrap()));
+
+    let _connection = published.connect();
+    let (res1, res2) = core.run(res1.join(res2)).unwrap();
+
+    assert_eq!(res1, [0, 1, 2, 3]);
+    assert_eq!(res2, [0, 1, 2, 3]);
+}
+
+
+#[test]
+fn drop_connection_while_polling() {
+    lazy(|| {
+        let (stream, victim) = drop_on_poll(iter_ok::<_, ()>(0..3));
+        let published = stream.unsync_publish();
+        let mut rx = published.subscribe();
+        *victim.borrow_mut() = Some(Box::new(published.connect()));
+
+        // Original stream drops the only connection while it is polled.
+        assert_eq!(rx.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(rx.poll(), Ok(Async::NotReady));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn share_auto() {
+    let created = Rc::new(AtomicUsize::new(0));
+    let dropped = Rc::new(AtomicUsize::new(0));
+
+    let (created2, dropped2) = (created.clone(), dropped.clone());
+    let feed = ex_futures::stream::unsync_share_auto(move || {
+        created2.fetch_add(1, Ordering::SeqCst);
+        DropFlag {
+            stream: unfold(0, |i| Some(ok::<(usize, usize), u8>((i, i + 1)))),
+            dropped: dropped2.clone(),
+        }
+    });
+
+    let rx1 = feed.subscribe();
+    let rx2 = feed.clone().subscribe();
+    assert_eq!(created.load(Ordering::SeqCst), 1);
+    assert_eq!(rx1.clone_count(), 2);
+
+    assert_eq!(rx1.take(2).collect().wait(), Ok(vec![0, 1]));
+    assert_eq!(dropped.load(Ordering::SeqCst), 0);
+    drop(rx2);
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+
+    // Original stream is created again.
+    let rx3 = feed.subscribe();
+    assert_eq!(created.load(Ordering::SeqCst), 2);
+    assert_eq!(rx3.take(2).collect().wait(), Ok(vec![0, 1]));
+    assert_eq!(dropped.load(Ordering::SeqCst), 2);
+}
+
+
+
+#[test]
+fn share_auto_after_end() {
+    let created = Rc::new(AtomicUsize::new(0));
+
+    let created2 = created.clone();
+    let feed = ex_futures::stream::unsync_share_auto(move || {
+        created2.fetch_add(1, Ordering::SeqCst);
+        iter_ok::<_, ()>(0..2)
+    });
+
+    // The first subscriber is still living after original stream finishes.
+    let rx1 = feed.subscribe();
+    assert_eq!(rx1.clone().collect().wait(), Ok(vec![0, 1]));
+    assert!(rx1.is_terminated());
+
+    // Original stream is created again.
+    let rx2 = feed.subscribe();
+    assert_eq!(created.load(Ordering::SeqCst), 2);
+    assert_eq!(rx2.collect().wait(), Ok(vec![0, 1]));
+    drop(rx1);
+}
+
+#[test]
+fn lossy() {
+    lazy(|| {
+        let stream = unfold(0, |i| Some(ok::<(usize, usize), u8>((i, i + 1))));
+        let mut main = stream.unsync_cloneable_bounded(2);
+        let mut diag = main.clone_lossy(2);
+        assert!(!main.is_lossy());
+        assert!(diag.is_lossy());
+        assert!(diag.clone().is_lossy());
+
+        // The lossy clone does not throttle the main one.
+        for i in 0..5 {
+            assert_eq!(main.poll(), Ok(Async::Ready(Some(i))));
+        }
+        assert_eq!(diag.queue_len(), 2);
+        assert_eq!(diag.dropped(), 3);
+
+        assert_eq!(diag.poll(), Ok(Async::Ready(Some(3))));
+        assert_eq!(diag.poll(), Ok(Async::Ready(Some(4))));
+
+        // The main clone still throttles original stream.
+        let mut diag2 = diag.clone();
+        assert_eq!(diag2.poll(), Ok(Async::Ready(Some(5))));
+        assert_eq!(diag2.poll(), Ok(Async::Ready(Some(6))));
+        assert_eq!(diag2.poll(), Ok(Async::NotReady));
+        assert_eq!(main.poll(), Ok(Async::Ready(Some(5))));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn budget() {
+    let mut stream = iter_ok::<_, ()>(0..3).unsync_cloneable();
+    stream.set_budget(1);
+    let clone = stream.clone();
+
+    assert_eq!(stream.collect().wait(), Ok(vec![0, 1, 2]));
+    assert_eq!(clone.collect().wait(), Ok(vec![0, 1, 2]));
+}
+
+
+#[test]
+fn inherit_budget() {
+    let mut stream = iter_ok::<_, ()>(0..3).unsync_cloneable();
+    stream.set_budget(4);
+
+    // A clone created by a weak handle inherits the budget.
+    let weak = stream.downgrade();
+    let upgraded = weak.upgrade().unwrap();
+    assert!(format!("{:?}", upgraded).contains("budget: 4"));
+
+    let publisher = iter_ok::<_, ()>(0..3).unsync_publish();
+    publisher.set_budget(5);
+    let mut subscriber = publisher.subscribe();
+    assert!(format!("{:?}", subscriber).contains("budget: 5"));
+
+    // So does a subscriber after another subscriber sets its budget.
+    subscriber.set_budget(6);
+    assert!(format!("{:?}", publisher.subscribe()).contains("budget: 6"));
+}
+
+
+#[test]
+#[should_panic(expected = "budget must be positive")]
+fn zero_budget() {
+    iter_ok::<_, ()>(Vec::<usize>::new()).unsync_cloneable().set_budget(0);
+}
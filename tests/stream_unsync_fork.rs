@@ -2,14 +2,24 @@ extern crate ex_futures;
 extern crate futures;
 extern crate tokio_core;
 
-use futures::{Future, Stream};
-use futures::stream::unfold;
-use futures::future::ok;
+mod common;
+
+use futures::{Future, Stream, Async};
+use futures::stream::{unfold, iter_ok, iter_result};
+use futures::future::{ok, lazy};
+use futures::executor;
 
 use ex_futures::StreamExt;
+use ex_futures::stream::{Route, Multicast, Branches};
+
+use common::{drop_on_poll, flag};
 
 use tokio_core::reactor::Core;
 
+use std::sync::Arc;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 
 
 #[test]
@@ -73,3 +83,415 @@ fn join() {
     assert_eq!(res1, [1, 3]);
     assert_eq!(res2, [0, 2]);
 }
+
+
+#[test]
+fn introspection() {
+    lazy(|| {
+        let (mut even, odd) = iter_ok::<_, u8>(0..3).unsync_fork(|i| i % 2 == 0);
+        assert_eq!(even.dispatched(), 0);
+
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(even.queue_len(), 0);
+        assert_eq!(odd.queue_len(), 1);
+        assert_eq!(odd.dispatched(), 3);
+        assert!(!odd.is_terminated());
+
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+        assert!(odd.is_terminated());
+        assert_eq!(odd.queue_len(), 2);
+
+        let debug = format!("{:?}", odd);
+        assert!(debug.contains("queue_len: 2"));
+        assert!(debug.contains("dispatched: 3"));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[derive(Clone, Copy)]
+enum Level {
+    Low,
+    Middle,
+    High,
+}
+
+impl<T> Route<T> for Level {
+    fn route<P: FnMut(usize, T)>(self, item: T, mut push: P) {
+        push(self as usize, item)
+    }
+}
+
+
+#[test]
+fn fork_n() {
+    let stream = iter_ok::<_, u8>(vec![3, 12, 25, 7, 30]);
+
+    let mut branches = stream.unsync_fork_n(3, |i| if *i < 10 {
+        Level::Low
+    } else if *i < 20 {
+        Level::Middle
+    } else {
+        Level::High
+    });
+    assert_eq!(branches[1].index(), Level::Middle as usize);
+
+    let high = branches.pop().unwrap();
+    let middle = branches.pop().unwrap();
+    let low = branches.pop().unwrap();
+
+    assert_eq!(high.collect().wait(), Ok(vec![25, 30]));
+    assert_eq!(low.collect().wait(), Ok(vec![3, 7]));
+    assert_eq!(middle.collect().wait(), Ok(vec![12]));
+}
+
+
+#[test]
+fn multicast() {
+    let stream = iter_ok::<_, u8>(0..6usize);
+
+    let (left, right) = stream.unsync_fork(|i| match i % 3 {
+        0 => Multicast::Both,
+        1 => Multicast::Left,
+        _ => Multicast::Neither,
+    });
+
+    let (left, right) = left.collect().join(right.collect()).wait().unwrap();
+    assert_eq!(left, [0, 1, 3, 4]);
+    assert_eq!(right, [0, 3]);
+}
+
+
+#[test]
+fn discard_partly_routed_item_on_panic() {
+    let stream = iter_ok::<_, u8>(0..2usize);
+
+    let mut branches = stream.unsync_fork_n(2, |i| if *i == 0 {
+        // Goes to the first branch, and then panics for the missing branch.
+        Branches::new().with(0).with(5)
+    } else {
+        Branches::new().with(0)
+    });
+    let _second = branches.pop().unwrap();
+    let mut first = branches.pop().unwrap();
+
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        lazy(|| first.poll()).wait()
+    }));
+    assert!(res.is_err());
+
+    assert_eq!(first.collect().wait(), Ok(vec![1]));
+}
+
+
+#[test]
+fn bounded() {
+    lazy(|| {
+        let (mut even, mut odd) = iter_ok::<_, u8>(0..6).unsync_fork_bounded(1, |i| i % 2 == 0);
+
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+
+        // `3` does not fit into the queue of `odd` which already holds `1`.
+        assert_eq!(even.poll(), Ok(Async::NotReady));
+        assert_eq!(odd.queue_len(), 1);
+        assert_eq!(even.dispatched(), 4);
+
+        assert_eq!(odd.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(odd.queue_len(), 1);
+
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(4))));
+        assert_eq!(even.poll(), Ok(Async::NotReady));
+
+        assert_eq!(odd.poll(), Ok(Async::Ready(Some(3))));
+        assert_eq!(odd.poll(), Ok(Async::Ready(Some(5))));
+        assert_eq!(odd.poll(), Ok(Async::Ready(None)));
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn bounded_wakes_blocked_branch() {
+    let (tx, rx) = futures::sync::mpsc::unbounded::<usize>();
+    for i in 0..4 {
+        tx.unbounded_send(i).unwrap();
+    }
+    drop(tx);
+
+    let (left, right) = rx.unsync_fork_bounded(1, |i| *i < 2);
+
+    // `right` can not read anything until `left` reads its queue.
+    let (right, left) = right.collect().join(left.collect()).wait().unwrap();
+    assert_eq!(left, [0, 1]);
+    assert_eq!(right, [2, 3]);
+}
+
+
+#[test]
+fn discard_items_of_dropped_branch() {
+    lazy(|| {
+        let (mut even, odd) = iter_ok::<_, u8>(0..6).unsync_fork(|i| i % 2 == 0);
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(odd.queue_len(), 1);
+
+        drop(odd);
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(4))));
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+        assert_eq!(even.dispatched(), 6);
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn fallback() {
+    let mut branches = iter_ok::<_, u8>(0..6).unsync_fork_n(3, |i| i % 3);
+    let rem2 = branches.pop().unwrap();
+    let rem1 = branches.pop().unwrap();
+    let rem0 = branches.pop().unwrap();
+
+    rem0.set_fallback();
+    drop(rem1);
+
+    assert_eq!(rem0.collect().wait(), Ok(vec![0, 1, 3, 4]));
+    assert_eq!(rem2.collect().wait(), Ok(vec![2, 5]));
+}
+
+
+#[test]
+fn dropped_branch_unblocks_bounded_fork() {
+    lazy(|| {
+        let (mut left, right) = iter_ok::<_, u8>(0..4).unsync_fork_bounded(1, |i| *i >= 2);
+        assert_eq!(left.poll(), Ok(Async::NotReady));
+
+        drop(right);
+        assert_eq!(left.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(left.poll(), Ok(Async::Ready(Some(3))));
+        assert_eq!(left.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+struct DropFlag<S> {
+    stream: S,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl<S: Stream> Stream for DropFlag<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<S::Item>, S::Error> {
+        self.stream.poll()
+    }
+}
+
+impl<S> Drop for DropFlag<S> {
+    fn drop(&mut self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+
+#[test]
+fn drop_upstream_with_last_branch() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let stream = DropFlag {
+        stream: iter_ok::<_, u8>(0..4),
+        dropped: dropped.clone(),
+    };
+
+    let (left, right) = stream.unsync_fork(|i| i % 2 == 0);
+    drop(left);
+    assert_eq!(dropped.load(Ordering::SeqCst), 0);
+
+    drop(right);
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+}
+
+
+#[test]
+fn drop_branch_while_polling() {
+    lazy(|| {
+        let (stream, victim) = drop_on_poll(iter_ok::<_, ()>(0..6));
+        let (mut even, odd) = stream.unsync_fork_bounded(1, |i| i % 2 == 0);
+        *victim.borrow_mut() = Some(Box::new(odd));
+
+        // Items of the branch dropped by original stream are discarded.
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(4))));
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[derive(Debug, PartialEq)]
+struct NotClone(usize);
+
+
+#[test]
+fn errors_to_branch() {
+    lazy(|| {
+        let stream = iter_result(vec![Ok(0), Err(NotClone(1)), Ok(2)]);
+        let (mut left, mut right) = stream.unsync_fork_with_errors(|_| true, 1);
+
+        assert_eq!(left.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(left.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(left.poll(), Ok(Async::Ready(None)));
+        assert_eq!(right.poll(), Err(NotClone(1)));
+        assert_eq!(right.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn error_router() {
+    lazy(|| {
+        let stream = iter_result(vec![Err(NotClone(0)), Err(NotClone(3)), Ok(1)]);
+        let mut branches = stream.unsync_fork_n_with_errors(3, |i| *i, |e: &NotClone| e.0 % 3);
+        let mut rem2 = branches.pop().unwrap();
+        let mut rem1 = branches.pop().unwrap();
+        let mut rem0 = branches.pop().unwrap();
+
+        assert_eq!(rem1.poll(), Ok(Async::Ready(Some(1))));
+        assert_eq!(rem0.poll(), Err(NotClone(0)));
+        assert_eq!(rem0.poll(), Err(NotClone(3)));
+        assert_eq!(rem2.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn error_stream() {
+    lazy(|| {
+        let stream = iter_result(vec![Ok(0), Err(NotClone(1)), Ok(2), Err(NotClone(3))]);
+        let (mut branches, mut errors) = stream.unsync_fork_n_with_error_stream(2, |i| i % 2);
+        let odd = branches.pop().unwrap();
+        let mut even = branches.pop().unwrap();
+
+        assert_eq!(errors.poll(), Ok(Async::Ready(Some(NotClone(1)))));
+        assert_eq!(even.queue_len(), 1);
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(0))));
+        assert_eq!(even.poll(), Ok(Async::Ready(Some(2))));
+        assert_eq!(even.poll(), Ok(Async::Ready(None)));
+        assert_eq!(odd.queue_len(), 1);
+        assert_eq!(errors.poll(), Ok(Async::Ready(Some(NotClone(3)))));
+        assert_eq!(errors.poll(), Ok(Async::Ready(None)));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+}
+
+
+#[test]
+fn notify_branch_receiving_item() {
+    let (tx, rx) = futures::sync::mpsc::unbounded::<usize>();
+    let (left, right) = rx.unsync_fork(|i| i % 2 == 0);
+    let mut left = executor::spawn(left);
+    let mut right = executor::spawn(right);
+    let (left_flag, right_flag) = (flag(), flag());
+
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::NotReady));
+    // Original stream notifies only `left` from now.
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::NotReady));
+
+    tx.unbounded_send(1).unwrap();
+    assert!(left_flag.0.load(Ordering::SeqCst));
+    assert!(!right_flag.0.load(Ordering::SeqCst));
+
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::NotReady));
+    assert!(right_flag.0.load(Ordering::SeqCst));
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::Ready(Some(1))));
+
+    right_flag.0.store(false, Ordering::SeqCst);
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::NotReady));
+    drop(tx);
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::Ready(None)));
+    assert!(right_flag.0.load(Ordering::SeqCst));
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::Ready(None)));
+}
+
+
+#[test]
+fn notify_others_on_drop() {
+    let (tx, rx) = futures::sync::mpsc::unbounded::<usize>();
+    let (left, right) = rx.unsync_fork(|i| i % 2 == 0);
+    let mut left = executor::spawn(left);
+    let mut right = executor::spawn(right);
+    let (left_flag, right_flag) = (flag(), flag());
+
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::NotReady));
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::NotReady));
+
+    // `right` must poll original stream instead of `left`.
+    drop(left);
+    assert!(right_flag.0.load(Ordering::SeqCst));
+
+    tx.unbounded_send(1).unwrap();
+    assert_eq!(right.poll_stream_notify(&right_flag, 0), Ok(Async::Ready(Some(1))));
+}
+
+
+#[test]
+fn yield_after_budget() {
+    let (mut left, right) = iter_ok::<_, ()>(0..4).unsync_fork(|_| false);
+    left.set_budget(2);
+    let mut left = executor::spawn(left);
+    let left_flag = flag();
+
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::NotReady));
+    assert!(left_flag.0.load(Ordering::SeqCst));
+    assert_eq!(right.queue_len(), 2);
+
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::NotReady));
+    assert_eq!(right.queue_len(), 4);
+    assert_eq!(left.poll_stream_notify(&left_flag, 0), Ok(Async::Ready(None)));
+}
+
+
+#[test]
+fn long_run_of_other_branch() {
+    let (left, right) = iter_ok::<_, ()>(0..100_000).unsync_fork(|_| false);
+
+    assert_eq!(left.collect().wait(), Ok(vec![]));
+    assert_eq!(right.collect().wait().map(|items| items.len()), Ok(100_000));
+}
+
+
+#[test]
+fn multi_task() {
+    let mut core = Core::new().unwrap();
+
+    let (tx, rx) = futures::sync::mpsc::unbounded::<usize>();
+    std::thread::spawn(move || for i in 0..8 {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        tx.unbounded_send(i).unwrap();
+    });
+
+    let (even, odd) = rx.unsync_fork(|i| i % 2 == 0);
+
+    let (odd_tx, odd_rx) = futures::sync::oneshot::channel();
+    core.handle().spawn(odd.collect().then(|res| {
+        let _ = odd_tx.send(res);
+        Ok(())
+    }));
+
+    assert_eq!(core.run(even.collect()).unwrap(), [0, 2, 4, 6]);
+    assert_eq!(core.run(odd_rx).unwrap().unwrap(), [1, 3, 5, 7]);
+}
@@ -0,0 +1,125 @@
+extern crate ex_futures;
+extern crate futures;
+extern crate tokio_core;
+
+use ex_futures::unsync::pubsub::Bridge;
+
+use futures::{Future, Stream, Async};
+use futures::future::{ok, lazy};
+
+use tokio_core::reactor::Core;
+
+use std::sync::mpsc::channel;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+
+
+#[test]
+fn forward_to_other_thread() {
+    let bridge = Bridge::<usize>::new();
+    let bridge2 = bridge.clone();
+    let (ready_tx, ready_rx) = channel();
+
+    let handle = thread::spawn(move || {
+        let mut core = Core::new().unwrap();
+        let (_tx, rx, import) = bridge2.join();
+        core.handle().spawn(import);
+        ready_tx.send(()).unwrap();
+        core.run(rx.take(4).map(|i| *i).collect()).unwrap()
+    });
+
+    ready_rx.recv().unwrap();
+
+    let (tx, rx, _import) = bridge.join();
+    for i in 0..4 {
+        tx.unbounded_send(i).unwrap();
+    }
+
+    assert_eq!(handle.join().unwrap(), [0, 1, 2, 3]);
+    assert_eq!(rx.take(4).map(|i| *i).collect().wait().unwrap(), [0, 1, 2, 3]);
+}
+
+
+#[test]
+fn both_directions() {
+    let bridge = Bridge::<usize>::new();
+    let bridge2 = bridge.clone();
+    let (ready_tx, ready_rx) = channel();
+    let (done_tx, done_rx) = channel();
+
+    let handle = thread::spawn(move || {
+        let mut core = Core::new().unwrap();
+        let (tx, rx, import) = bridge2.join();
+        core.handle().spawn(import);
+        ready_tx.send(()).unwrap();
+
+        let received = core.run(rx.take(2).map(|i| *i).collect()).unwrap();
+        tx.unbounded_send(42).unwrap();
+        done_rx.recv().unwrap();
+        received
+    });
+
+    ready_rx.recv().unwrap();
+
+    let mut core = Core::new().unwrap();
+    let (tx, rx, import) = bridge.join();
+    core.handle().spawn(import);
+
+    tx.unbounded_send(0).unwrap();
+    tx.unbounded_send(1).unwrap();
+
+    // Local subscriber sees own items and then the reply, but no echo.
+    assert_eq!(core.run(rx.take(3).map(|i| *i).collect()).unwrap(), [0, 1, 42]);
+    done_tx.send(()).unwrap();
+
+    assert_eq!(handle.join().unwrap(), [0, 1]);
+}
+
+
+#[test]
+fn prune_dropped_peer() {
+    let bridge = Bridge::<usize>::new();
+
+    let (_tx, _rx, import) = bridge.join();
+    let (_tx2, _rx2, import2) = bridge.join();
+    assert_eq!(bridge.peer_count(), 2);
+
+    drop(import2);
+    assert_eq!(bridge.peer_count(), 1);
+    drop(import);
+    assert_eq!(bridge.peer_count(), 0);
+}
+
+
+#[derive(Debug)]
+struct PanicOnClone(bool);
+
+impl Clone for PanicOnClone {
+    fn clone(&self) -> Self {
+        assert!(!self.0, "clone panics");
+        PanicOnClone(false)
+    }
+}
+
+
+#[test]
+fn survive_panic_while_forwarding() {
+    let bridge = Bridge::<PanicOnClone>::new();
+
+    let (tx, _rx, _import) = bridge.join();
+    let (_tx2, rx2, mut import2) = bridge.join();
+
+    let res = panic::catch_unwind(AssertUnwindSafe(|| tx.unbounded_send(PanicOnClone(true))));
+    assert!(res.is_err());
+
+    // The bridge still works.
+    tx.unbounded_send(PanicOnClone(false)).unwrap();
+    assert_eq!(bridge.peer_count(), 2);
+    lazy(|| {
+        assert_eq!(import2.poll(), Ok(Async::NotReady));
+        ok::<(), ()>(())
+    }).wait()
+        .unwrap();
+    assert!(!rx2.take(1).collect().wait().unwrap()[0].0);
+}
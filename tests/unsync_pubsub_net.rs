@@ -0,0 +1,143 @@
+#![cfg(feature = "net")]
+
+extern crate ex_futures;
+extern crate futures;
+extern crate tokio_core;
+
+use ex_futures::unsync::pubsub::unbounded;
+use ex_futures::unsync::pubsub::net::{serve, subscribe, Codec};
+
+use futures::{Future, Stream, Async};
+use futures::future::poll_fn;
+
+use tokio_core::reactor::Core;
+use tokio_core::net::{TcpListener, TcpStream};
+
+use std::io::{self, Write};
+use std::net;
+use std::rc::Rc;
+use std::cell::Cell;
+
+
+
+#[derive(Clone)]
+struct Utf8;
+
+impl Codec for Utf8 {
+    type Item = String;
+
+    fn encode(&mut self, item: &String, buf: &mut Vec<u8>) -> io::Result<()> {
+        buf.extend_from_slice(item.as_bytes());
+        Ok(())
+    }
+
+    fn decode(&mut self, buf: &[u8]) -> io::Result<String> {
+        String::from_utf8(buf.to_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+
+/// Drives `server` until it accepts `n` connections. Items published before that are not sent.
+fn wait_for_accept<F: Future>(core: &mut Core, server: &mut F, accepted: &Cell<usize>, n: usize) {
+    core.run(poll_fn(|| {
+        server.poll().map_err(|_| ())?;
+        if accepted.get() < n {
+            Ok::<_, ()>(Async::NotReady)
+        } else {
+            Ok(Async::Ready(()))
+        }
+    })).unwrap();
+}
+
+
+
+#[test]
+fn loopback() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let accepted = Rc::new(Cell::new(0));
+    let accepted2 = accepted.clone();
+    let incoming = listener.incoming().map(move |(sock, _)| {
+        accepted2.set(accepted2.get() + 1);
+        sock
+    });
+
+    let (tx, rx) = unbounded::<String>();
+    let mut server = serve(incoming, rx, Utf8);
+
+    let sock = core.run(TcpStream::connect(&addr, &handle)).unwrap();
+    let subscriber = subscribe(sock, Utf8);
+    wait_for_accept(&mut core, &mut server, &accepted, 1);
+    handle.spawn(server.map_err(|_| ()));
+
+    for s in &["foo", "", "bar"] {
+        tx.unbounded_send(s.to_string()).unwrap();
+    }
+    drop(tx);
+
+    assert_eq!(core.run(subscriber.collect()).unwrap(), ["foo", "", "bar"]);
+}
+
+
+#[test]
+fn many_subscribers() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let accepted = Rc::new(Cell::new(0));
+    let accepted2 = accepted.clone();
+    let incoming = listener.incoming().map(move |(sock, _)| {
+        accepted2.set(accepted2.get() + 1);
+        sock
+    });
+
+    let (tx, rx) = unbounded::<String>();
+    let mut server = serve(incoming, rx, Utf8);
+
+    let sock1 = core.run(TcpStream::connect(&addr, &handle)).unwrap();
+    let sock2 = core.run(TcpStream::connect(&addr, &handle)).unwrap();
+    let subscriber1 = subscribe(sock1, Utf8);
+    let subscriber2 = subscribe(sock2, Utf8);
+    wait_for_accept(&mut core, &mut server, &accepted, 2);
+    handle.spawn(server.map_err(|_| ()));
+
+    tx.unbounded_send("hello".to_string()).unwrap();
+    drop(tx);
+
+    let joined = subscriber1.collect().join(subscriber2.collect());
+    let (res1, res2) = core.run(joined).unwrap();
+
+    assert_eq!(res1, ["hello"]);
+    assert_eq!(res2, ["hello"]);
+}
+
+
+#[test]
+fn oversized_frame() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let sock = core.run(TcpStream::connect(&addr, &handle)).unwrap();
+    let mut subscriber = subscribe(sock, Utf8);
+    subscriber.set_max_frame_len(16);
+
+    // A peer announces a 4 GiB frame.
+    let (mut peer, _) = listener.accept().unwrap();
+    peer.write_all(&[0xff, 0xff, 0xff, 0xff]).unwrap();
+
+    let err = match core.run(subscriber.into_future()) {
+        Ok(_) => panic!("oversized frame is accepted"),
+        Err((err, _)) => err,
+    };
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}